@@ -0,0 +1,167 @@
+use crate::{markdown, settings};
+
+/// Markdown with its admonitions expanded.
+pub struct Expanded {
+    pub text: String,
+    /// The 0-based line of the source each line of `text` comes from, since the `<aside>` tags
+    /// take more lines than the markers they replace.
+    pub lines: Vec<usize>,
+}
+
+/// Rewrites `> [!NOTE]` blockquotes and `:::warning Optional title` ... `:::` containers into
+/// `<aside>` blocks before the Markdown is parsed, leaving their contents to the parser.
+/// Fenced code blocks are left as is.
+pub fn expand(content: &str) -> Expanded {
+    let mut expanded = Expanded {
+        text: String::with_capacity(content.len()),
+        lines: Vec::new(),
+    };
+    let mut open_containers = 0;
+    let mut line_nb = 0;
+
+    for region in markdown::fenced_regions(content) {
+        let text = &content[region.range];
+
+        match region.is_code {
+            true => {
+                for (i, line) in text.lines().enumerate() {
+                    expanded.push_line(line, line_nb + i);
+                }
+            }
+            false => expanded.expand_text(text, line_nb, &mut open_containers),
+        }
+
+        line_nb += text.lines().count();
+    }
+
+    for _ in 0..open_containers {
+        expanded.push(CLOSE_TAG, line_nb.saturating_sub(1));
+    }
+
+    expanded
+}
+
+const CLOSE_TAG: &str = "\n</aside>\n\n";
+
+impl Expanded {
+    /// Expands the admonitions in `text`, which has no fenced code and starts at line `first` of
+    /// the source. `:::` containers may be closed after the code that follows, so the number
+    /// still open is kept in `open_containers`.
+    fn expand_text(&mut self, text: &str, first: usize, open_containers: &mut usize) {
+        let mut lines = text.lines().enumerate().peekable();
+
+        while let Some((i, line)) = lines.next() {
+            let line_nb = first + i;
+            let trimmed = line.trim_start();
+
+            if let Some(kind) = blockquote_kind(trimmed) {
+                let mut body = String::new();
+                let mut last = line_nb;
+
+                while let Some((j, next)) = lines.peek() {
+                    let next = next.trim_start();
+
+                    if !next.starts_with('>') {
+                        break;
+                    }
+
+                    body += next[1..].strip_prefix(' ').unwrap_or(&next[1..]);
+                    body += "\n";
+                    last = first + j;
+                    lines.next();
+                }
+
+                let inner = expand(&body);
+
+                self.push(&open_tag(&kind, None), line_nb);
+                self.text += &inner.text;
+                self.lines.extend(
+                    inner
+                        .lines
+                        .iter()
+                        .map(|inner_line| line_nb + 1 + inner_line),
+                );
+                self.push(CLOSE_TAG, last);
+            } else if let Some(rest) = trimmed.strip_prefix(":::") {
+                let rest = rest.trim();
+
+                if rest.is_empty() && *open_containers > 0 {
+                    *open_containers -= 1;
+                    self.push(CLOSE_TAG, line_nb);
+                } else if !rest.is_empty() {
+                    let (kind, title) = match rest.find(char::is_whitespace) {
+                        Some(i) => (&rest[..i], Some(rest[i..].trim())),
+                        None => (rest, None),
+                    };
+
+                    *open_containers += 1;
+                    self.push(&open_tag(&kind.to_lowercase(), title), line_nb);
+                } else {
+                    self.push_line(line, line_nb);
+                }
+            } else {
+                self.push_line(line, line_nb);
+            }
+        }
+    }
+
+    /// Appends `text`, made of whole lines, which all come from line `line_nb` of the source.
+    fn push(&mut self, text: &str, line_nb: usize) {
+        self.text += text;
+        self.lines.extend(text.matches('\n').map(|_| line_nb));
+    }
+
+    fn push_line(&mut self, line: &str, line_nb: usize) {
+        self.text += line;
+        self.text += "\n";
+        self.lines.push(line_nb);
+    }
+
+    /// The 0-based source line of the 0-based `line` of the expanded text. Lines past the end,
+    /// such as the end of a file without a final newline, follow the last line.
+    pub fn source_line(&self, line: usize) -> usize {
+        match (self.lines.get(line), self.lines.last()) {
+            (Some(source_line), _) => *source_line,
+            (None, Some(last)) => last + line + 1 - self.lines.len(),
+            (None, None) => line,
+        }
+    }
+}
+
+/// Returns the lowercased kind of a `> [!KIND]` line.
+fn blockquote_kind(line: &str) -> Option<String> {
+    let marker = line.strip_prefix('>')?.trim();
+    let kind = marker.strip_prefix("[!")?.strip_suffix(']')?;
+
+    match !kind.is_empty() && kind.chars().all(|c| c.is_ascii_alphabetic()) {
+        true => Some(kind.to_lowercase()),
+        false => None,
+    }
+}
+
+fn open_tag(kind: &str, title: Option<&str>) -> String {
+    let settings = settings::current();
+    let class = &settings.content.admonition_class;
+    let title_class = &settings.content.admonition_title_class;
+    let title = match title {
+        Some(title) if !title.is_empty() => title.to_string(),
+        _ => capitalize(kind),
+    };
+
+    format!(
+        "<aside class=\"{} {}\" role=\"note\">\n<p class=\"{}\">{}</p>\n\n",
+        class,
+        kind,
+        title_class,
+        tera::escape_html(&title)
+    )
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
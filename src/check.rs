@@ -0,0 +1,363 @@
+use crate::content::{self, FileType};
+use crate::errors::{BuildError, Diagnostics};
+use crate::links;
+use crate::models::Page;
+use crate::settings;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+const STATIC_PREFIX: &str = "/static/";
+const STATIC_PATH: &str = "./static";
+
+struct Tag {
+    name: String,
+    attributes: Vec<(String, String)>,
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Builds the site without serving it, then checks redirects and the links, anchors, static
+/// assets and images of every rendered page. Prints the problems found, as JSON if `json` is set,
+/// and returns whether there were no errors.
+pub fn run(json: bool) -> bool {
+    let mut diagnostics = Diagnostics::default();
+    let pages = content::build(&mut diagnostics);
+    let redirects = redirects();
+
+    check_redirects(&pages, &redirects, &mut diagnostics);
+
+    let html_pages = pages
+        .iter()
+        .filter(|(slug, page)| is_html(slug) && page.meta.rendered.is_some())
+        .map(|(slug, page)| (slug, tags(page.meta.rendered.as_ref().unwrap())))
+        .collect::<HashMap<&String, Vec<Tag>>>();
+
+    let ids = html_pages
+        .iter()
+        .map(|(slug, tags)| (slug.as_str(), anchors(tags)))
+        .collect::<HashMap<&str, HashSet<String>>>();
+
+    let mut slugs = html_pages.keys().collect::<Vec<&&String>>();
+    slugs.sort();
+
+    for slug in slugs {
+        let page = &pages[*slug];
+        let mut problems = Vec::new();
+
+        for tag in &html_pages[slug] {
+            check_tag(tag, page, &pages, &ids, &redirects, &mut problems);
+        }
+
+        // A link repeated on a page, say in its content and its summary, is reported once.
+        let mut reported = HashSet::new();
+
+        for (is_error, problem) in problems {
+            if !reported.insert(problem.message.clone()) {
+                continue;
+            }
+
+            match is_error {
+                true => diagnostics.report(problem),
+                false => diagnostics.warn(problem),
+            }
+        }
+    }
+
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&diagnostics).unwrap()),
+        false => diagnostics.summary(),
+    }
+
+    !diagnostics.has_errors()
+}
+
+/// Maps each redirect's `from` path to its `to` location.
+fn redirects() -> HashMap<String, String> {
+    settings::current()
+        .redirects
+        .iter()
+        .map(|(from, redirect)| (from.clone(), redirect.to.clone()))
+        .collect()
+}
+
+fn check_redirects(
+    pages: &HashMap<String, Page>,
+    redirects: &HashMap<String, String>,
+    diagnostics: &mut Diagnostics,
+) {
+    let mut sorted = redirects.iter().collect::<Vec<(&String, &String)>>();
+    sorted.sort();
+
+    for (from, to) in sorted {
+        if pages.contains_key(from) {
+            diagnostics.report(BuildError::new(format!(
+                "Redirect from {} never applies: a page is served at that path.",
+                from
+            )));
+        }
+
+        if !is_internal(to) {
+            continue;
+        }
+
+        let (path, _) = split_url(to);
+
+        if path == *from {
+            diagnostics.report(BuildError::new(format!(
+                "Redirect from {} points to itself.",
+                from
+            )));
+        } else if !pages.contains_key(&path) && !redirects.contains_key(&path) && !is_asset(&path) {
+            diagnostics.report(BuildError::new(format!(
+                "Redirect from {} points to {}, which doesn't exist.",
+                from, to
+            )));
+        }
+    }
+}
+
+/// Adds the problems found in `tag` to `problems`, flagged as errors or warnings.
+fn check_tag(
+    tag: &Tag,
+    page: &Page,
+    pages: &HashMap<String, Page>,
+    ids: &HashMap<&str, HashSet<String>>,
+    redirects: &HashMap<String, String>,
+    problems: &mut Vec<(bool, BuildError)>,
+) {
+    let source = &page.meta.source;
+
+    if tag.name == "img" && tag.attribute("alt").is_none() {
+        let src = tag.attribute("src").unwrap_or("");
+        let warning = BuildError::new(format!("Image {} has no alt text.", src));
+        problems.push((false, warning.at_mention(source, src)));
+    }
+
+    for url in ["href", "src"]
+        .iter()
+        .filter_map(|name| tag.attribute(name))
+    {
+        if !is_internal(url) && !is_relative(url) {
+            continue;
+        }
+
+        let (path, fragment) = split_url(&resolve_url(&page.slug, url));
+
+        let error = if path.starts_with(STATIC_PREFIX) {
+            match is_asset(&path) {
+                true => continue,
+                false => BuildError::new(format!("Missing static asset {}.", path)),
+            }
+        } else if pages.contains_key(&path) {
+            match (fragment, ids.get(path.as_str())) {
+                (Some(fragment), Some(page_ids)) if !page_ids.contains(&fragment) => {
+                    BuildError::new(format!("Broken anchor #{} in link to {}.", fragment, path))
+                }
+                _ => continue,
+            }
+        } else if redirects.contains_key(&path) {
+            continue;
+        } else {
+            BuildError::new(format!("Broken link to {}.", path))
+        };
+
+        problems.push((true, error.at_mention(source, url)));
+    }
+}
+
+fn is_html(slug: &str) -> bool {
+    matches!(content::get_filetype(slug), FileType::Html)
+}
+
+fn is_internal(url: &str) -> bool {
+    url.starts_with('/') && !url.starts_with("//")
+}
+
+/// Relative URLs have no scheme such as `https:` or `mailto:`. Bare `#fragment` links count.
+fn is_relative(url: &str) -> bool {
+    let scheme_len = url
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "+-.".contains(c)))
+        .unwrap_or(url.len());
+
+    !url.starts_with('/') && !url[scheme_len..].starts_with(':')
+}
+
+fn is_asset(path: &str) -> bool {
+    match path.strip_prefix(STATIC_PREFIX) {
+        Some(file) => Path::new(STATIC_PATH).join(file).is_file(),
+        None => false,
+    }
+}
+
+/// Resolves `url` against the page served at `slug`, as browsers do.
+fn resolve_url(slug: &str, url: &str) -> String {
+    if url.starts_with('/') {
+        return url.to_string();
+    }
+
+    if url.is_empty() || url.starts_with('#') || url.starts_with('?') {
+        return format!("{}{}", slug, url);
+    }
+
+    let mut segments = slug.split('/').collect::<Vec<&str>>();
+    segments.pop();
+
+    for segment in url.split('/') {
+        match segment {
+            "." => {}
+            ".." if segments.len() > 1 => {
+                segments.pop();
+            }
+            ".." => {}
+            segment => segments.push(segment),
+        }
+    }
+
+    segments.join("/")
+}
+
+/// Splits an internal URL into the slug it's served at and its decoded fragment, if any.
+fn split_url(url: &str) -> (String, Option<String>) {
+    let (path, fragment) = match url.find('#') {
+        Some(i) => (&url[..i], Some(links::percent_decode(&url[i + 1..]))),
+        None => (url, None),
+    };
+
+    let path = links::percent_decode(path.split('?').next().unwrap());
+    let path = match path.len() > 1 && path.ends_with('/') {
+        true => path[..path.len() - 1].to_string(),
+        false => path,
+    };
+
+    (path, fragment.filter(|fragment| !fragment.is_empty()))
+}
+
+/// Targets of `#fragment` links: `id` attributes and the `name` of `<a>` elements.
+fn anchors(tags: &[Tag]) -> HashSet<String> {
+    tags.iter()
+        .flat_map(|tag| {
+            let name = match tag.name.as_str() {
+                "a" => tag.attribute("name"),
+                _ => None,
+            };
+
+            tag.attribute("id").into_iter().chain(name)
+        })
+        .map(links::percent_decode)
+        .collect()
+}
+
+/// The start tags of an HTML document, skipping comments and the content of `<script>` and
+/// `<style>` elements.
+fn tags(html: &str) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+
+        if name_len == 0 {
+            continue;
+        }
+
+        let name = rest[..name_len].to_ascii_lowercase();
+        let (attributes, attributes_len) = parse_attributes(&rest[name_len..]);
+        rest = &rest[name_len + attributes_len..];
+
+        if name == "script" || name == "style" {
+            let end_tag = format!("</{}", name);
+            rest = rest.find(&end_tag).map_or("", |end| &rest[end..]);
+        }
+
+        tags.push(Tag { name, attributes });
+    }
+
+    tags
+}
+
+/// Parses attributes up to the end of a start tag, returning them and the length parsed.
+fn parse_attributes(tag: &str) -> (Vec<(String, String)>, usize) {
+    let mut attributes = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        pos += tag[pos..].len()
+            - tag[pos..]
+                .trim_start_matches(|c: char| c.is_whitespace() || c == '/')
+                .len();
+
+        if pos >= tag.len() {
+            return (attributes, pos);
+        }
+
+        if tag[pos..].starts_with('>') {
+            return (attributes, pos + 1);
+        }
+
+        let name_len = tag[pos..]
+            .find(|c: char| c.is_whitespace() || "=>/".contains(c))
+            .unwrap_or(tag.len() - pos);
+        let name = tag[pos..pos + name_len].to_ascii_lowercase();
+        pos += name_len;
+
+        let after_name = tag[pos..].trim_start();
+
+        let value = match after_name.strip_prefix('=') {
+            Some(after_equals) => {
+                let value_start = after_equals.trim_start();
+                pos = tag.len() - value_start.len();
+
+                match value_start.chars().next() {
+                    Some(quote) if quote == '"' || quote == '\'' => {
+                        let len = value_start[1..]
+                            .find(quote)
+                            .unwrap_or(value_start.len() - 1);
+                        pos += len + 2;
+                        &value_start[1..1 + len]
+                    }
+                    _ => {
+                        let len = value_start
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(value_start.len());
+                        pos += len;
+                        &value_start[..len]
+                    }
+                }
+            }
+            None => "",
+        };
+
+        pos = pos.min(tag.len());
+
+        if !name.is_empty() {
+            attributes.push((name, decode_entities(value)));
+        } else {
+            pos += 1;
+        }
+    }
+}
+
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
@@ -1,65 +1,1037 @@
-use crate::SETTINGS;
+use crate::errors::BuildError;
+use crate::settings::{self, Settings, SyntaxHighlighting};
 use pulldown_cmark::CowStr;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+use syntect::dumps::{dump_to_file, from_dump_file};
 use syntect::easy::HighlightLines;
-use syntect::highlighting::ThemeSet;
-use syntect::parsing::SyntaxSet;
+use syntect::highlighting::{Color, FontStyle, Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::{BasicScopeStackOp, ParseState, Scope, ScopeStack, ScopeStackOp};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
 
 lazy_static! {
-    pub static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
-    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+    pub static ref SYNTAX_SET: SyntaxSet = load_syntax_set();
+    static ref THEME_SET: ThemeSet = load_theme_set();
 }
 
-#[derive(Debug, Default, Clone)]
+const CACHE_DIR: &str = ".thea-cache";
+
+const HL_STYLES: [&str; 3] = ["highlight", "added", "removed"];
+
+/// Line comment openers recognised in callouts and include region markers.
+const COMMENT_TOKENS: [&str; 7] = ["//", "#", "--", ";", "%", "/*", "<!--"];
+
+/// Class added to `<pre>` and inline `<code>` elements highlighted with CSS classes; stylesheets
+/// are scoped to it.
+pub const HIGHLIGHT_CLASS: &str = "highlight";
+
+/// A callout number of a code block, with its annotation and how many lines refer to it.
+struct Callout {
+    nb: usize,
+    text: String,
+    refs: usize,
+}
+
+pub enum Highlighter<'a> {
+    Inline(HighlightLines<'a>),
+    Classed(ParseState, ScopeStack),
+}
+
+#[derive(Debug, Clone)]
 pub struct CodeBlockOpen {
     pub lang: Option<String>,
     pub filename: Option<String>,
-    pub highlights: Option<Vec<usize>>,
+    pub highlights: Option<Vec<(usize, usize)>>,
+    pub linenos: bool,
+    pub start: usize,
+    pub hl_style: String,
+    pub diff: bool,
+    pub callouts: bool,
+    pub include: Option<String>,
+    pub lines: Option<(usize, usize)>,
+    pub region: Option<String>,
+}
+
+impl Default for CodeBlockOpen {
+    fn default() -> CodeBlockOpen {
+        CodeBlockOpen {
+            lang: None,
+            filename: None,
+            highlights: None,
+            linenos: true,
+            start: 1,
+            hl_style: HL_STYLES[0].to_string(),
+            diff: false,
+            callouts: false,
+            include: None,
+            lines: None,
+            region: None,
+        }
+    }
+}
+
+impl CodeBlockOpen {
+    /// Whether the line numbered `line_nb` (counting from `start`) is highlighted.
+    pub fn is_highlighted(&self, line_nb: usize) -> bool {
+        match &self.highlights {
+            Some(highlights) => highlights
+                .iter()
+                .any(|&(from, to)| from <= line_nb && line_nb <= to),
+            None => false,
+        }
+    }
 }
 
 pub fn parse_codeblock_open<'a>(info: CowStr<'a>) -> CodeBlockOpen {
-    let parts = info.split_whitespace().collect::<Vec<&str>>();
     let mut codeblock_open: CodeBlockOpen = Default::default();
 
-    for p in parts {
-        if p.contains("=") == false {
-            codeblock_open.lang = Some(p.to_string());
-        } else if p.starts_with("filename=") {
-            let filename = &p[9..];
-            codeblock_open.filename = Some(filename.to_string());
-        } else if p.starts_with("highlight=") {
-            let lines = &p[10..];
-            let line_numbers = lines.split(",").collect::<Vec<&str>>();
-            let mut highlight = Vec::with_capacity(line_numbers.len());
-
-            for l in line_numbers {
-                match l.parse::<usize>() {
-                    Ok(line_nr) => highlight.push(line_nr),
-                    Err(e) => error!("Error parsing '{}' - {:?}", l, e),
-                };
+    for (key, value) in split_info(&info) {
+        let value = match value {
+            Some(value) => value,
+            None => {
+                // `rust,diff` is shorthand for `rust diff=true`.
+                let mut flags = key.split(',');
+                codeblock_open.lang = flags.next().map(str::to_string);
+
+                for flag in flags {
+                    match flag {
+                        "diff" => codeblock_open.diff = true,
+                        "callouts" => codeblock_open.callouts = true,
+                        _ => error!("Unknown code block flag '{}'", flag),
+                    }
+                }
+
+                continue;
             }
+        };
+
+        match key.as_ref() {
+            "filename" => codeblock_open.filename = Some(value),
+            "highlight" => {
+                let highlight = parse_line_ranges(&value);
 
-            match highlight.is_empty() {
-                true => codeblock_open.highlights = None,
-                false => codeblock_open.highlights = Some(highlight),
+                match highlight.is_empty() {
+                    true => codeblock_open.highlights = None,
+                    false => codeblock_open.highlights = Some(highlight),
+                }
             }
+            "linenos" => match value.parse::<bool>() {
+                Ok(linenos) => codeblock_open.linenos = linenos,
+                Err(e) => error!("Error parsing linenos '{}' - {:?}", value, e),
+            },
+            "diff" => match value.parse::<bool>() {
+                Ok(diff) => codeblock_open.diff = diff,
+                Err(e) => error!("Error parsing diff '{}' - {:?}", value, e),
+            },
+            "callouts" => match value.parse::<bool>() {
+                Ok(callouts) => codeblock_open.callouts = callouts,
+                Err(e) => error!("Error parsing callouts '{}' - {:?}", value, e),
+            },
+            "include" => codeblock_open.include = Some(value),
+            "lines" => match parse_line_range(&value) {
+                Some(lines) => codeblock_open.lines = Some(lines),
+                None => error!("Error parsing lines '{}' - expected N or N-M", value),
+            },
+            "region" => codeblock_open.region = Some(value),
+            "start" => match value.parse::<usize>() {
+                Ok(start) => codeblock_open.start = start,
+                Err(e) => error!("Error parsing start '{}' - {:?}", value, e),
+            },
+            "hl_style" => match HL_STYLES.contains(&value.as_ref()) {
+                true => codeblock_open.hl_style = value,
+                false => error!(
+                    "Invalid hl_style '{}'. Valid values: {}",
+                    value,
+                    HL_STYLES.join("/")
+                ),
+            },
+            _ => error!("Unknown code block option '{}'", key),
         }
     }
 
     codeblock_open
 }
 
-pub fn get_highlighter<'a>(codeblock: &CodeBlockOpen) -> HighlightLines<'a> {
-    let config_theme = SETTINGS.get_str("content.syntax_theme").unwrap();
-    let theme = &THEME_SET.themes[&config_theme];
+/// Splits a code block info string into `key` / `key=value` pairs. Values may be wrapped in
+/// double quotes to include whitespace, e.g. `filename="src/my file.rs"`.
+fn split_info(info: &str) -> Vec<(String, Option<String>)> {
+    let mut parts = Vec::new();
+    let mut chars = info.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        let mut value = None;
+
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => break,
+                '=' => {
+                    value = Some(read_value(&mut chars));
+                    break;
+                }
+                c => key.push(c),
+            }
+        }
+
+        parts.push((key, value));
+    }
+
+    parts
+}
+
+fn read_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut value = String::new();
+
+    if chars.peek() == Some(&'"') {
+        chars.next();
+
+        for c in chars {
+            match c {
+                '"' => break,
+                c => value.push(c),
+            }
+        }
+    } else {
+        for c in chars {
+            match c {
+                c if c.is_whitespace() => break,
+                c => value.push(c),
+            }
+        }
+    }
+
+    value
+}
+
+/// Parses a single line number or inclusive range, e.g. `10-30`.
+fn parse_line_range(lines: &str) -> Option<(usize, usize)> {
+    let (from, to) = match lines.find('-') {
+        Some(i) => (lines[..i].parse().ok()?, lines[i + 1..].parse().ok()?),
+        None => (lines.parse().ok()?, lines.parse().ok()?),
+    };
+
+    match from > 0 && from <= to {
+        true => Some((from, to)),
+        false => None,
+    }
+}
+
+/// Reads the file named by the `include` option relative to the content root, narrowed down to
+/// `lines` and/or `region`. The language defaults to the file's extension. Returns the code and
+/// the path of the included file, which must be inside the site's directory.
+pub fn read_include(cbo: &mut CodeBlockOpen) -> Result<(String, PathBuf), String> {
+    let include = cbo.include.clone().unwrap();
+    let failed = |e: String| format!("Failed to include '{}': {}", include, e);
+    let site_root = env::current_dir().and_then(|dir| dir.canonicalize());
+    let path = settings::current()
+        .content
+        .path
+        .join(&include)
+        .canonicalize();
+
+    let path = match (site_root, path) {
+        (Ok(site_root), Ok(path)) if path.starts_with(&site_root) => path,
+        (Ok(_), Ok(_)) => return Err(failed("the file is outside the site's directory.".into())),
+        (Err(e), _) | (_, Err(e)) => return Err(failed(e.to_string())),
+    };
+
+    let contents = fs::read_to_string(&path).map_err(|e| failed(e.to_string()))?;
+
+    let mut lines = contents.lines().collect::<Vec<&str>>();
+
+    if let Some((from, to)) = cbo.lines {
+        if from > lines.len() {
+            return Err(format!(
+                "Failed to include '{}': lines {}-{} are out of range ({} lines).",
+                include,
+                from,
+                to,
+                lines.len()
+            ));
+        }
+
+        lines = lines[from - 1..to.min(lines.len())].to_vec();
+    }
+
+    if let Some(region) = &cbo.region {
+        lines = extract_region(&lines, region).ok_or_else(|| {
+            format!(
+                "Failed to include '{}': region '{}' not found.",
+                include, region
+            )
+        })?;
+    }
+
+    if cbo.lang.is_none() {
+        cbo.lang = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_string);
+    }
+
+    Ok((dedent(&lines), path))
+}
+
+/// Returns the lines between `region: <name>` and `endregion: <name>` marker comments. Lines
+/// holding markers of other regions are dropped.
+fn extract_region<'a>(lines: &[&'a str], region: &str) -> Option<Vec<&'a str>> {
+    let start = lines
+        .iter()
+        .position(|l| region_marker(l) == Some(("region", region)))?;
+    let end = start
+        + lines[start..]
+            .iter()
+            .position(|l| region_marker(l) == Some(("endregion", region)))?;
+
+    let region_lines = lines[start + 1..end]
+        .iter()
+        .filter(|l| region_marker(l).is_none())
+        .cloned()
+        .collect();
+
+    Some(region_lines)
+}
+
+/// Parses a line holding only a `region: <name>` or `endregion: <name>` comment into the marker
+/// kind and the region name.
+fn region_marker(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    let token = COMMENT_TOKENS
+        .iter()
+        .find(|token| line.starts_with(*token))?;
+    let comment = line[token.len()..]
+        .trim_end_matches("*/")
+        .trim_end_matches("-->")
+        .trim();
+    let colon = comment.find(':')?;
+    let kind = &comment[..colon];
+    let name = comment[colon + 1..].trim();
+
+    match (kind, name) {
+        ("region", name) | ("endregion", name)
+            if !name.is_empty() && !name.contains(char::is_whitespace) =>
+        {
+            Some((kind, name))
+        }
+        _ => None,
+    }
+}
+
+/// Joins the lines after removing the indentation they have in common.
+fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .map(|l| format!("{}\n", l.get(indent..).unwrap_or("").trim_end()))
+        .collect()
+}
+
+/// Parses comma-separated line numbers and inclusive ranges, e.g. `2-6,9`, into `(from, to)`
+/// pairs. Ranges are kept as bounds rather than expanded so `1-999999999` costs nothing.
+fn parse_line_ranges(lines: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+
+    for l in lines.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+        let range = match l.find('-') {
+            Some(i) => (l[..i].parse::<usize>(), l[i + 1..].parse::<usize>()),
+            None => (l.parse::<usize>(), l.parse::<usize>()),
+        };
+
+        match range {
+            (Ok(from), Ok(to)) if from <= to => ranges.push((from, to)),
+            (Ok(_), Ok(_)) => error!("Error parsing '{}' - range start is after its end", l),
+            (Err(e), _) | (_, Err(e)) => error!("Error parsing '{}' - {:?}", l, e),
+        };
+    }
+
+    ranges
+}
+
+/// Loads the default syntaxes plus any `.sublime-syntax` files in `content.syntaxes_path`. The
+/// compiled set is cached in `.thea-cache` and reused until a syntax file changes.
+fn load_syntax_set() -> SyntaxSet {
+    let settings = settings::current();
+    let syntaxes_path = &settings.content.syntaxes_path;
+
+    if !syntaxes_path.is_dir() {
+        return SyntaxSet::load_defaults_newlines();
+    }
+
+    let cache_path = syntax_cache_path(syntaxes_path);
+
+    if let Ok(syntax_set) = from_dump_file(&cache_path) {
+        return syntax_set;
+    }
+
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+
+    if let Err(e) = builder.add_from_folder(syntaxes_path, true) {
+        error!(
+            "Failed to load syntaxes from {}: {}",
+            syntaxes_path.display(),
+            e
+        );
+        process::exit(1);
+    }
+
+    let syntax_set = builder.build();
+    let cache_dir = cache_path.parent().unwrap();
+
+    // Drop dumps of previous versions of the syntax files.
+    let _ = fs::remove_dir_all(cache_dir);
+    let _ = fs::create_dir_all(cache_dir);
+
+    if let Err(e) = dump_to_file(&syntax_set, &cache_path) {
+        warn!("Failed to cache the compiled syntaxes: {}", e);
+    }
+
+    syntax_set
+}
+
+/// The cache file is named after a hash of every syntax file's path, size and modification time.
+fn syntax_cache_path(syntaxes_path: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    let mut entries = walkdir::WalkDir::new(syntaxes_path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension() == Some(OsStr::new("sublime-syntax")))
+        .collect::<Vec<walkdir::DirEntry>>();
+
+    entries.sort_by(|a, b| a.path().cmp(b.path()));
+
+    for entry in entries {
+        entry.path().hash(&mut hasher);
+
+        if let Ok(metadata) = entry.metadata() {
+            metadata.len().hash(&mut hasher);
+            metadata.modified().ok().hash(&mut hasher);
+        }
+    }
+
+    let mut cache_path = env::current_dir().unwrap();
+    cache_path.push(CACHE_DIR);
+    cache_path.push("syntaxes");
+    cache_path.push(format!("{:016x}.packdump", hasher.finish()));
+    cache_path
+}
+
+/// Loads the default themes plus any `.tmTheme` files in `content.themes_path`.
+fn load_theme_set() -> ThemeSet {
+    let settings = settings::current();
+    let themes_path = &settings.content.themes_path;
+    let mut theme_set = ThemeSet::load_defaults();
+
+    if themes_path.is_dir() {
+        if let Err(e) = theme_set.add_from_folder(themes_path) {
+            error!(
+                "Failed to load themes from {}: {}",
+                themes_path.display(),
+                e
+            );
+            process::exit(1);
+        }
+    }
+
+    theme_set
+}
+
+/// Exits with a list of the available themes if `content.syntax_theme` or one of
+/// `content.syntax_themes` doesn't exist.
+pub fn validate_themes() {
+    let errors = unknown_themes(&settings::current());
+
+    if errors.is_empty() {
+        return;
+    }
+
+    for e in errors {
+        error!("{}", e);
+    }
+
+    process::exit(1);
+}
+
+/// Reports `content.syntax_theme` and each of `content.syntax_themes` that doesn't exist.
+pub fn unknown_themes(settings: &Settings) -> Vec<BuildError> {
+    settings
+        .content
+        .syntax_themes
+        .iter()
+        .chain(Some(&settings.content.syntax_theme))
+        .filter(|theme| !THEME_SET.themes.contains_key(*theme))
+        .map(|theme| unknown_theme(theme))
+        .collect()
+}
+
+fn unknown_theme(theme: &str) -> BuildError {
+    let available = THEME_SET.themes.keys().cloned().collect::<Vec<String>>();
+
+    BuildError::new(format!(
+        "Unknown syntax theme '{}'. Available themes: {}",
+        theme,
+        available.join(", ")
+    ))
+}
+
+pub fn uses_classes() -> bool {
+    settings::current().content.syntax_highlighting == SyntaxHighlighting::Classes
+}
+
+/// Fails if `content.syntax_theme` doesn't exist, in case the settings weren't validated.
+pub fn get_highlighter<'a>(codeblock: &CodeBlockOpen) -> Result<Highlighter<'a>, BuildError> {
+    let syntax = find_syntax(codeblock.lang.as_ref());
+
+    if uses_classes() {
+        let state = ParseState::new(syntax);
+        return Ok(Highlighter::Classed(state, ScopeStack::new()));
+    }
+
+    let theme_name = &settings::current().content.syntax_theme;
+
+    match THEME_SET.themes.get(theme_name) {
+        Some(theme) => Ok(Highlighter::Inline(HighlightLines::new(syntax, theme))),
+        None => Err(unknown_theme(theme_name)),
+    }
+}
+
+fn find_syntax(lang: Option<&String>) -> &'static SyntaxReference {
+    match lang {
+        Some(lang) => SYNTAX_SET
+            .find_syntax_by_token(lang)
+            .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text()),
+        None => SYNTAX_SET.find_syntax_plain_text(),
+    }
+}
+
+impl<'a> Highlighter<'a> {
+    /// Highlights a single line, including its trailing newline if any, carrying the parse state
+    /// over from the previous lines. The returned HTML is balanced and has no trailing newline.
+    pub fn highlight_line(&mut self, line: &str) -> String {
+        match self {
+            Highlighter::Inline(highlighter) => {
+                let mut hltd = highlighter.highlight(line, &SYNTAX_SET);
+
+                if let Some(last) = hltd.last_mut() {
+                    last.1 = last.1.trim_end_matches('\n');
+                }
+
+                styled_line_to_highlighted_html(&hltd, IncludeBackground::No)
+            }
+            Highlighter::Classed(state, stack) => {
+                let ops = state.parse_line(line, &SYNTAX_SET);
+                let mut html = classed_html(line, &ops, stack);
+                html.truncate(html.trim_end_matches('\n').len());
+                html
+            }
+        }
+    }
+}
+
+/// Renders a fenced code block: the optional filename followed by one `<div class="line">` per
+/// line of highlighted code and, when the block enables `callouts`, the list of annotations.
+/// `index` distinguishes the callout ids of the blocks on a page.
+pub fn render(cbo: &CodeBlockOpen, code: &str, index: usize) -> Result<String, BuildError> {
+    let mut highlighter = get_highlighter(cbo)?;
+    let mut callouts: Vec<Callout> = Vec::new();
+    let mut html = String::new();
+
+    if let Some(filename) = &cbo.filename {
+        let _ = write!(
+            html,
+            "<span class=\"pre-filename\">{}</span>",
+            tera::escape_html(filename)
+        );
+    };
+
+    let mut classes = Vec::new();
+
+    if let Some(lang) = &cbo.lang {
+        classes.push(format!("lang-{}", lang));
+    }
+
+    if uses_classes() {
+        classes.push(HIGHLIGHT_CLASS.to_string());
+    }
+
+    match classes.is_empty() {
+        true => html += "<pre><code>",
+        false => {
+            let _ = write!(html, "<pre class=\"{}\"><code>", classes.join(" "));
+        }
+    };
+
+    for (i, mut line) in LinesWithEndings::from(code).enumerate() {
+        let line_nb = cbo.start + i;
+        let mut classes = vec!["line".to_string()];
+        let mut diff_marker = None;
+
+        if cbo.is_highlighted(line_nb) {
+            classes.push(format!("line-{}", cbo.hl_style));
+        }
+
+        if cbo.diff {
+            match line.chars().next() {
+                Some('+') => classes.push("line-added".to_string()),
+                Some('-') => classes.push("line-removed".to_string()),
+                _ => {}
+            };
+
+            if let Some(marker @ '+') | Some(marker @ '-') | Some(marker @ ' ') =
+                line.chars().next()
+            {
+                diff_marker = Some(marker);
+                line = &line[1..];
+            }
+        }
+
+        let callout = match cbo.callouts {
+            true => split_callout(line),
+            false => None,
+        };
+        let code_line = match &callout {
+            Some((code, _, _)) => format!("{}{}", code, &line[line.trim_end().len()..]),
+            None => line.to_string(),
+        };
+
+        let _ = write!(html, "<div class=\"{}\">", classes.join(" "));
+
+        if cbo.linenos {
+            let _ = write!(html, "<span class=\"line-nb\">{}</span>", line_nb);
+        }
+
+        if let Some(marker) = diff_marker {
+            let _ = write!(html, "<span class=\"diff-marker\">{}</span>", marker);
+        }
+
+        html += &highlighter.highlight_line(&code_line);
+
+        if let Some((_, nb, text)) = callout {
+            let id = format!("cb{}-callout-{}", index, nb);
+            let refs = match callouts.iter_mut().find(|c| c.nb == nb) {
+                Some(callout) => {
+                    callout.refs += 1;
+
+                    if callout.text.is_empty() {
+                        callout.text = text;
+                    }
+
+                    callout.refs
+                }
+                None => {
+                    callouts.push(Callout { nb, text, refs: 1 });
+                    1
+                }
+            };
+
+            // Lines sharing a number link to the same annotation, which links back to the first.
+            let ref_id = match refs {
+                1 => format!("{}-ref", id),
+                n => format!("{}-ref-{}", id, n),
+            };
+
+            let _ = write!(
+                html,
+                "<a class=\"callout\" id=\"{}\" href=\"#{}\">{}</a>",
+                ref_id, id, nb
+            );
+        }
+
+        html += "</div>";
+    }
+
+    html += "</code></pre>";
+
+    if !callouts.is_empty() {
+        html += "<ol class=\"callouts\">";
+
+        for Callout { nb, text, .. } in callouts {
+            let id = format!("cb{}-callout-{}", index, nb);
+            let _ = write!(
+                html,
+                "<li id=\"{}\" value=\"{}\"><a class=\"callout\" href=\"#{}-ref\">{}</a> {}</li>",
+                id,
+                nb,
+                id,
+                nb,
+                tera::escape_html(&text)
+            );
+        }
+
+        html += "</ol>";
+    }
+
+    Ok(html)
+}
+
+/// Renders inline code written as `` `let x = 1;`{:rust} `` as `<code class="lang-rust">` with
+/// highlighted spans.
+pub fn render_inline(code: &str, lang: &str) -> Result<String, BuildError> {
+    let cbo = CodeBlockOpen {
+        lang: Some(lang.to_string()),
+        ..Default::default()
+    };
+
+    let mut highlighter = get_highlighter(&cbo)?;
+    let classes = match uses_classes() {
+        true => format!("lang-{} {}", lang, HIGHLIGHT_CLASS),
+        false => format!("lang-{}", lang),
+    };
+
+    Ok(format!(
+        "<code class=\"{}\">{}</code>",
+        classes,
+        highlighter.highlight_line(&format!("{}\n", code))
+    ))
+}
+
+/// Splits a `// <1> Optional annotation` callout off the end of a line, returning the code
+/// before the comment, the callout number and the annotation text. Supports `//`, `#`, `--`, `;`,
+/// `%`, `/* */` and `<!-- -->` comments.
+fn split_callout(line: &str) -> Option<(&str, usize, String)> {
+    let line = line.trim_end();
+    let mut search_end = line.len();
 
-    match codeblock.lang {
-        Some(ref lang) => {
-            let syntax = SYNTAX_SET
-                .find_syntax_by_token(lang)
-                .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    while let Some(open) = line[..search_end].rfind('<') {
+        search_end = open;
+        let close = match line[open..].find('>') {
+            Some(close) => open + close,
+            None => continue,
+        };
 
-            HighlightLines::new(syntax, theme)
+        let nb = match line[open + 1..close].parse::<usize>() {
+            Ok(nb) => nb,
+            Err(_) => continue,
+        };
+
+        let before = line[..open].trim_end();
+        let comment_start = COMMENT_TOKENS
+            .iter()
+            .find(|token| before.ends_with(*token))
+            .map(|token| before.len() - token.len());
+
+        if let Some(start) = comment_start {
+            let text = line[close + 1..]
+                .trim()
+                .trim_end_matches("*/")
+                .trim_end_matches("-->")
+                .trim()
+                .to_string();
+
+            return Some((line[..start].trim_end(), nb, text));
+        }
+    }
+
+    None
+}
+
+/// Renders a line as `<span>`s with one class per scope atom. Spans still open at the end of the
+/// line are closed before its newline and reopened at the start of the next, so every line is
+/// balanced.
+fn classed_html(line: &str, ops: &[(usize, ScopeStackOp)], stack: &mut ScopeStack) -> String {
+    let text_end = line.trim_end_matches('\n').len();
+    let mut html = String::with_capacity(line.len() * 2);
+    let mut cur_index = 0;
+
+    for scope in stack.as_slice() {
+        open_classed_span(&mut html, *scope);
+    }
+
+    for (i, op) in ops {
+        let i = (*i).min(text_end);
+
+        if i > cur_index {
+            html += &tera::escape_html(&line[cur_index..i]);
+            cur_index = i;
         }
-        None => HighlightLines::new(SYNTAX_SET.find_syntax_plain_text(), theme),
+
+        stack.apply_with_hook(op, |basic_op, _| match basic_op {
+            BasicScopeStackOp::Push(scope) => open_classed_span(&mut html, scope),
+            BasicScopeStackOp::Pop => html += "</span>",
+        });
+    }
+
+    html += &tera::escape_html(&line[cur_index..text_end]);
+
+    for _ in stack.as_slice() {
+        html += "</span>";
+    }
+
+    html += &line[text_end..];
+    html
+}
+
+fn open_classed_span(html: &mut String, scope: Scope) {
+    let _ = write!(
+        html,
+        "<span class=\"{}\">",
+        scope.build_string().replace('.', " ")
+    );
+}
+
+/// Generates a stylesheet for the classes emitted when `content.syntax_highlighting` is
+/// `classes`. Only theme rules targeting a single scope are translated.
+pub fn css_for_theme(name: &str) -> Option<String> {
+    let theme = THEME_SET.themes.get(name)?;
+    let mut css = format!("/* Syntax theme: {} */\n", name);
+
+    let _ = write!(css, ".{} {{", HIGHLIGHT_CLASS);
+    write_theme_colors(&mut css, theme);
+    css += " }\n";
+
+    for item in &theme.scopes {
+        let selectors = item
+            .scope
+            .selectors
+            .iter()
+            .filter_map(|selector| selector.extract_single_scope())
+            .map(|scope| format!(".{} .{}", HIGHLIGHT_CLASS, scope.build_string()))
+            .collect::<Vec<String>>();
+
+        if selectors.is_empty() {
+            continue;
+        }
+
+        css += &selectors.join(", ");
+        css += " {";
+
+        if let Some(fg) = item.style.foreground {
+            let _ = write!(css, " color: {};", css_color(fg));
+        }
+
+        if let Some(bg) = item.style.background {
+            let _ = write!(css, " background-color: {};", css_color(bg));
+        }
+
+        if let Some(fs) = item.style.font_style {
+            let weight = if fs.contains(FontStyle::BOLD) {
+                "bold"
+            } else {
+                "normal"
+            };
+            let italic = if fs.contains(FontStyle::ITALIC) {
+                "italic"
+            } else {
+                "normal"
+            };
+            let underline = if fs.contains(FontStyle::UNDERLINE) {
+                "underline"
+            } else {
+                "none"
+            };
+            let _ = write!(
+                css,
+                " font-weight: {}; font-style: {}; text-decoration: {};",
+                weight, italic, underline
+            );
+        }
+
+        css += " }\n";
+    }
+
+    Some(css)
+}
+
+fn write_theme_colors(css: &mut String, theme: &Theme) {
+    if let Some(fg) = theme.settings.foreground {
+        let _ = write!(css, " color: {};", css_color(fg));
+    }
+
+    if let Some(bg) = theme.settings.background {
+        let _ = write!(css, " background-color: {};", css_color(bg));
+    }
+}
+
+fn css_color(c: Color) -> String {
+    match c.a {
+        0xff => format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b),
+        a => format!(
+            "rgba({}, {}, {}, {:.3})",
+            c.r,
+            c.g,
+            c.b,
+            f32::from(a) / 255.0
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Multi-line constructs that leave scopes open across lines.
+    const SAMPLES: &[(&str, &str)] = &[
+        (
+            "rust",
+            "/* a block\n   comment */\nlet s = r#\"raw\nstring\"#;\nlet t = \"multi\n  line\";\n",
+        ),
+        ("c", "/*\n * doc\n */\nint main() { return 0; }\n"),
+        (
+            "python",
+            "def f():\n    \"\"\"Doc\n    string.\n    \"\"\"\n    return '''a\nb'''\n",
+        ),
+        (
+            "bash",
+            "cat <<EOF\nhello $USER\n  <tag> & more\nEOF\necho done\n",
+        ),
+        (
+            "ruby",
+            "text = <<~EOS\n  heredoc #{x}\nEOS\n=begin\nblock\n=end\n",
+        ),
+        ("js", "const s = `template\n${x} literal`;\n/* c\n*/\n"),
+        ("html", "<!-- a\ncomment -->\n<p class=\"x\n y\">hi</p>\n"),
+    ];
+
+    fn assert_balanced(lang: &str, html: &str) {
+        let mut depth = 0;
+
+        for tag in html.split('<').skip(1) {
+            if tag.starts_with("span") {
+                depth += 1;
+            } else if tag.starts_with("/span>") {
+                assert!(depth > 0, "{}: unbalanced line {:?}", lang, html);
+                depth -= 1;
+            }
+        }
+
+        assert_eq!(depth, 0, "{}: unbalanced line {:?}", lang, html);
+    }
+
+    fn highlight_all(mut highlighter: Highlighter, lang: &str, code: &str) {
+        for line in LinesWithEndings::from(code) {
+            let html = highlighter.highlight_line(line);
+            assert!(
+                !html.ends_with('\n'),
+                "{}: trailing newline in {:?}",
+                lang,
+                html
+            );
+            assert_balanced(lang, &html);
+        }
+    }
+
+    #[test]
+    fn inline_lines_are_balanced() {
+        let theme = &THEME_SET.themes["InspiredGitHub"];
+
+        for (lang, code) in SAMPLES {
+            let syntax = find_syntax(Some(&lang.to_string()));
+            assert_ne!(syntax.name, "Plain Text", "no syntax for {}", lang);
+            highlight_all(
+                Highlighter::Inline(HighlightLines::new(syntax, theme)),
+                lang,
+                code,
+            );
+        }
+    }
+
+    #[test]
+    fn classed_lines_are_balanced() {
+        for (lang, code) in SAMPLES {
+            let syntax = find_syntax(Some(&lang.to_string()));
+            highlight_all(
+                Highlighter::Classed(ParseState::new(syntax), ScopeStack::new()),
+                lang,
+                code,
+            );
+        }
+    }
+
+    #[test]
+    fn rendered_blocks_wrap_balanced_lines() {
+        for (lang, code) in SAMPLES {
+            let cbo = CodeBlockOpen {
+                lang: Some(lang.to_string()),
+                ..CodeBlockOpen::default()
+            };
+            let html = render(&cbo, code, 0).unwrap();
+
+            assert_eq!(
+                html.matches("<div class=\"line").count(),
+                code.lines().count()
+            );
+
+            for line in html.split("<div class=\"line\">").skip(1) {
+                assert_balanced(lang, line.split("</div>").next().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn huge_highlight_ranges_are_not_expanded() {
+        let cbo = parse_codeblock_open("rust highlight=2-3,7,1-999999999999".into());
+
+        assert_eq!(
+            cbo.highlights,
+            Some(vec![(2, 3), (7, 7), (1, 999_999_999_999)])
+        );
+        assert!(cbo.is_highlighted(500_000));
+
+        let cbo = parse_codeblock_open("rust highlight=2-3,7".into());
+
+        assert!(cbo.is_highlighted(3) && cbo.is_highlighted(7));
+        assert!(!cbo.is_highlighted(1) && !cbo.is_highlighted(4));
+    }
+
+    #[test]
+    fn include_regions_match_whole_markers() {
+        let lines = [
+            "// region: setup_db",
+            "let db = 1;",
+            "// endregion: setup_db",
+            "# region: setup",
+            "let s = \"region: setup\";",
+            "    /* region: inner */",
+            "x",
+            "    /* endregion: inner */",
+            "// see region: setup",
+            "<!-- endregion: setup -->",
+        ];
+
+        assert_eq!(
+            extract_region(&lines, "setup"),
+            Some(vec![
+                "let s = \"region: setup\";",
+                "x",
+                "// see region: setup"
+            ])
+        );
+        assert_eq!(extract_region(&lines, "setup_d"), None);
+    }
+
+    #[test]
+    fn callouts_are_opt_in_and_numbered_once() {
+        let code = "x = 1  # <1> Set x\nprint(x) # <1>\ny = 2 # <2>\n";
+        let plain = parse_codeblock_open("python".into());
+        let html = render(&plain, code, 1).unwrap();
+
+        assert!(!html.contains("callout"));
+        assert!(html.contains("&lt;1&gt;"));
+
+        let annotated = parse_codeblock_open("python,callouts".into());
+        let html = render(&annotated, code, 1).unwrap();
+
+        assert_eq!(html.matches("id=\"cb1-callout-1-ref\"").count(), 1);
+        assert_eq!(html.matches("id=\"cb1-callout-1-ref-2\"").count(), 1);
+        assert_eq!(html.matches("<li id=\"cb1-callout-1\"").count(), 1);
+        assert_eq!(html.matches("<li id=\"cb1-callout-2\"").count(), 1);
+        assert!(html.contains("</a> Set x</li>"));
     }
 }
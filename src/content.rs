@@ -1,17 +1,21 @@
+use crate::errors::{BuildError, Diagnostics};
+use crate::formats::{self, Format};
+use crate::markdown::{self, RenderOptions};
 use crate::models::{ConfigPageType, Page, PageMeta};
-use crate::{markdown, SETTINGS};
-use config::Value as ConfigValue;
+use crate::sections::{self, Section};
+use crate::{codeblocks, frontmatter, links, settings, slugs, summary};
 use html_minifier::HTMLMinifier;
 use std::collections::HashMap;
 use std::error::Error;
-use std::io::{Error as IoError, ErrorKind, Write};
-use std::path::Path;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::process;
 use std::{env, fs};
-use tera::{Context as TeraContext, Map as TeraMap, Tera, Value as TeraValue};
+use tera::{Context as TeraContext, Map as TeraMap, Template, Tera, Value as TeraValue};
 use uuid::Uuid;
 use walkdir::{DirEntry, WalkDir};
-use yaml_rust::{Yaml, YamlLoader};
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::Yaml;
 
 pub enum FileType {
     Html,
@@ -22,18 +26,26 @@ pub enum FileType {
     Txt,
 }
 
-trait IntoPageType {
-    fn into_page_type(&self) -> Option<ConfigPageType>;
+pub fn build_hashmap() -> HashMap<String, Page> {
+    let mut diagnostics = Diagnostics::default();
+    let pages = build(&mut diagnostics);
+
+    diagnostics.summary();
+    pages
 }
 
-pub fn build_hashmap() -> HashMap<String, Page> {
-    let mut hashmap = HashMap::new();
-    let base_content_path = SETTINGS.get_str("content.path").unwrap();
-    let page_types = get_page_types();
+/// Parses and renders every page, collecting the problems found into `diagnostics`.
+pub fn build(diagnostics: &mut Diagnostics) -> HashMap<String, Page> {
+    let mut hashmap: HashMap<String, Page> = HashMap::new();
+    let settings = settings::current();
+    let templates = build_templates();
 
-    for pt in &page_types {
-        let walk_path = format!("{}/{}", base_content_path, &pt.path);
-        let walker = WalkDir::new(walk_path).into_iter();
+    for pt in &settings.content.page_types {
+        let walk_path = settings.content.path.join(&pt.path);
+        // Sorted so the same file wins when several claim a slug.
+        let walker = WalkDir::new(&walk_path)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter();
 
         for entry in walker {
             let entry = entry.unwrap();
@@ -41,77 +53,114 @@ pub fn build_hashmap() -> HashMap<String, Page> {
                 continue;
             }
 
-            let default_layout = pt.default_layout.clone();
-            let ttype = pt.ttype.clone();
+            let rel_path = entry.path().strip_prefix(&walk_path).unwrap();
 
-            let page = match parse_file_at(entry.path(), default_layout, ttype) {
+            let page = match parse_file_at(entry.path(), rel_path, pt, &templates) {
                 Ok(page) => page,
                 Err(e) => {
-                    error!("For: {} - {:?}", entry.path().display(), e);
+                    diagnostics.report(e.in_file(entry.path()));
                     continue;
                 }
             };
 
-            hashmap.insert(page.slug.clone(), page);
+            insert_unique(&mut hashmap, page, diagnostics);
         }
     }
 
-    render_pages(hashmap)
-}
-
-fn get_page_types() -> Vec<ConfigPageType> {
-    let config_page_types = SETTINGS
-        .get_array("content.page_types")
-        .unwrap_or_else(|_| {
-            error!("Failed while collecting content.page_types.");
-            Vec::with_capacity(0)
-        });
-
-    let mut page_types = Vec::with_capacity(config_page_types.len());
-
-    for pt in &config_page_types {
-        match pt.into_page_type() {
-            Some(pt) => page_types.push(pt),
-            None => error!("{:?} has missing parameters; skipping", pt),
+    if codeblocks::uses_classes() {
+        for page in syntax_stylesheets(diagnostics) {
+            insert_unique(&mut hashmap, page, diagnostics);
         }
     }
 
-    page_types
+    links::resolve(&mut hashmap, diagnostics);
+    let sections = sections::assign(&mut hashmap);
+    render_pages(hashmap, &sections, &templates, diagnostics)
 }
 
-fn is_cachable(entry: &DirEntry) -> bool {
-    let supported_extensions = ["md", "html", "css", "js", "json", "xml", "txt"];
+/// Adds `page` to `hashmap`, reporting and skipping it if another page already uses its slug.
+fn insert_unique(hashmap: &mut HashMap<String, Page>, page: Page, diagnostics: &mut Diagnostics) {
+    let existing = match hashmap.get(&page.slug) {
+        Some(existing) => existing,
+        None => {
+            hashmap.insert(page.slug.clone(), page);
+            return;
+        }
+    };
 
-    match entry.path().extension() {
-        Some(ext) => supported_extensions.contains(&ext.to_str().unwrap()),
-        None => false,
-    }
-}
+    let cause = format!("already used by {}", describe_source(existing));
+    let error = match page.meta.source.as_os_str().is_empty() {
+        true => BuildError::new(format!(
+            "Duplicate slug {}; skipping the generated syntax stylesheet.",
+            page.slug
+        )),
+        false => BuildError::new(format!("Duplicate slug {}; skipping this file.", page.slug))
+            .in_file(&page.meta.source),
+    };
 
-impl IntoPageType for ConfigValue {
-    fn into_page_type(&self) -> Option<ConfigPageType> {
-        let table = self.clone().into_table().unwrap();
+    diagnostics.report(error.with_cause(cause));
+}
 
-        let ttype = match table.get("type") {
-            Some(ttype) => ttype.to_string(),
-            None => return None,
-        };
+/// The file a page was read from, or a description of generated pages, which have none.
+fn describe_source(page: &Page) -> String {
+    match page.meta.source.as_os_str().is_empty() {
+        true => String::from("the generated syntax stylesheet"),
+        false => page.meta.source.display().to_string(),
+    }
+}
 
-        let path = match table.get("path") {
-            Some(path) => path.to_string(),
-            None => return None,
-        };
+/// Builds a `/syntax/<theme>.css` page for each of `content.syntax_themes`, or for
+/// `content.syntax_theme` if that list is empty.
+fn syntax_stylesheets(diagnostics: &mut Diagnostics) -> Vec<Page> {
+    let settings = settings::current();
+    let themes = match settings.content.syntax_themes.is_empty() {
+        true => std::slice::from_ref(&settings.content.syntax_theme),
+        false => &settings.content.syntax_themes[..],
+    };
 
-        let default_layout = match table.get("default_layout") {
-            Some(default_layout) => default_layout.to_string(),
-            None => return None,
-        };
+    themes
+        .iter()
+        .filter_map(|theme| {
+            let css = match codeblocks::css_for_theme(theme) {
+                Some(css) => css,
+                None => {
+                    diagnostics.report(BuildError::new(format!(
+                        "Unknown syntax theme '{}'; skipping its stylesheet.",
+                        theme
+                    )));
+                    return None;
+                }
+            };
 
-        Some(ConfigPageType {
-            ttype: ttype,
-            path: path,
-            default_layout: default_layout,
+            Some(Page {
+                page_type: "syntax".to_string(),
+                slug: format!("/syntax/{}.css", slugs::slugify(theme)),
+                content: css,
+                summary: String::new(),
+                word_count: 0,
+                reading_time: 0,
+                toc: Vec::new(),
+                fm: TeraMap::new(),
+                is_section: false,
+                section: None,
+                ancestors: Vec::new(),
+                backlinks: Vec::new(),
+                meta: PageMeta {
+                    etag: Uuid::new_v4().to_string(),
+                    layout: None,
+                    rendered: None,
+                    source: PathBuf::new(),
+                    dependencies: Vec::new(),
+                },
+            })
         })
+        .collect()
+}
+
+fn is_cachable(entry: &DirEntry) -> bool {
+    match entry.path().extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => formats::lookup(ext).is_some(),
+        None => false,
     }
 }
 
@@ -132,138 +181,281 @@ pub fn get_filetype(slug: &str) -> FileType {
     }
 }
 
-fn parse_file_at(path: &Path, default_layout: String, ttype: String) -> Result<Page, IoError> {
+fn parse_file_at(
+    path: &Path,
+    rel_path: &Path,
+    pt: &ConfigPageType,
+    templates: &Tera,
+) -> Result<Page, BuildError> {
     let file_contents = fs::read_to_string(path)?;
-    let (fm_start, fm_end, content_start) = find_frontmatter(&file_contents)?;
-    let frontmatter = &file_contents[fm_start..fm_end];
-    let frontmatter_as_yaml = parse_frontmatter(frontmatter)?;
-    let content = &file_contents[content_start..];
     let extension_str = path.extension().unwrap().to_str().unwrap();
 
-    let parsed_content = match extension_str {
-        "md" => markdown::from(content),
-        "html" | "css" | "js" | "json" | "xml" | "txt" => content.to_string(),
-        _ => {
-            return Err(IoError::new(
-                ErrorKind::Other,
-                "File has an unsupported extension.",
-            ))
+    let format = match formats::lookup(extension_str) {
+        Some(format) => format,
+        None => return Err(BuildError::new("File has an unsupported extension.")),
+    };
+
+    // Frontmatter is optional for raw files such as stylesheets, which are then served as is.
+    let (frontmatter_as_yaml, content_start, has_frontmatter) = match format {
+        Format::Document { metadata, .. } => (
+            document_frontmatter(path, &file_contents, *metadata)?,
+            0,
+            true,
+        ),
+        Format::Raw => match frontmatter::split(&file_contents, false)? {
+            (Some(yaml), content_start) => (yaml, content_start, true),
+            (None, content_start) => (Yaml::Hash(YamlHash::new()), content_start, false),
+        },
+        _ => match frontmatter::split(&file_contents, true)? {
+            (Some(yaml), content_start) => (yaml, content_start, true),
+            (None, _) => {
+                return Err(BuildError::new("Failed to find frontmatter.")
+                    .with_cause("expected the file to start with ---, +++ or a JSON object"))
+            }
+        },
+    };
+
+    let content = &file_contents[content_start..];
+    let line_offset = file_contents[..content_start].matches('\n').count();
+    let md_options = RenderOptions::new(path, line_offset, templates, &frontmatter_as_yaml)?;
+    let mut md_summary = None;
+    let mut toc = Vec::new();
+    let mut dependencies = Vec::new();
+
+    let rendered = match format {
+        Format::Markup(to_markdown) => Some(markdown::from(&to_markdown(content), &md_options)?),
+        Format::Document { render, .. } => {
+            Some(render(content, &frontmatter_as_yaml, &md_options)?)
         }
+        Format::Html | Format::Raw => None,
     };
 
-    let err = |k| {
-        IoError::new(
-            ErrorKind::Other,
-            format!("Missing required {} key in frontmatter.", k),
-        )
+    let parsed_content = match rendered {
+        Some(rendered) => {
+            md_summary = rendered.summary;
+            toc = rendered.toc;
+            dependencies = rendered.dependencies;
+
+            rendered.html
+        }
+        None => content.to_string(),
     };
 
+    let is_section = sections::is_section_index(path);
+
     let page_slug = match frontmatter_as_yaml["slug"].as_str() {
         Some(slug) => slug.to_string(),
-        None => return Err(err("slug")),
+        None => match &pt.permalink {
+            Some(permalink) if !is_section => {
+                slugs::expand_permalink(permalink, rel_path, &frontmatter_as_yaml)?
+            }
+            _ => slugs::derive_slug(rel_path),
+        },
     };
 
     let page_meta_layout = match frontmatter_as_yaml["layout"].as_str() {
-        Some(layout) => layout.to_string(),
-        None => default_layout,
+        Some(layout) => Some(layout.to_string()),
+        None if has_frontmatter => Some(pt.default_layout.clone()),
+        None => None,
     };
 
-    let fm_dump = dump_frontmatter(frontmatter_as_yaml);
+    let fm_dump = dump_frontmatter(frontmatter_as_yaml)?;
+    let summary = summary::from(content, &parsed_content, format, md_summary);
 
     Ok(Page {
-        page_type: ttype,
+        page_type: pt.ttype.clone(),
         slug: page_slug,
         fm: fm_dump,
         content: parsed_content,
+        summary: summary.summary,
+        word_count: summary.word_count,
+        reading_time: summary.reading_time,
+        toc,
+        is_section,
+        section: None,
+        ancestors: Vec::new(),
+        backlinks: Vec::new(),
         meta: PageMeta {
             etag: Uuid::new_v4().to_string(),
-            layout: Some(page_meta_layout),
+            layout: page_meta_layout,
             rendered: None,
+            source: path.to_path_buf(),
+            dependencies,
         },
     })
 }
 
-fn find_frontmatter(content: &str) -> Result<(usize, usize, usize), IoError> {
-    let err = IoError::new(ErrorKind::Other, "Failed to find frontmatter.");
+/// Frontmatter of files that can't carry any, such as notebooks: the keys extracted by the
+/// format's `metadata` function, overridden by those of a sidecar file such as `data.csv.yml`.
+fn document_frontmatter(
+    path: &Path,
+    contents: &str,
+    metadata: fn(&str) -> Result<Yaml, BuildError>,
+) -> Result<Yaml, BuildError> {
+    let mut fm = metadata(contents)?.into_hash().unwrap_or_default();
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".yml");
+
+    let sidecar_contents = match fs::read_to_string(&sidecar) {
+        Ok(sidecar_contents) => sidecar_contents,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(BuildError::from(e).in_file(Path::new(&sidecar))),
+    };
 
-    match content.starts_with("---\n") {
-        true => {
-            let fm = &content[4..];
-            let fm_end = match fm.find("---\n") {
-                Some(i) => i,
-                None => return Err(err),
-            };
-            Ok((4, fm_end + 4, fm_end + 8))
+    if !sidecar_contents.trim().is_empty() {
+        let sidecar_fm = frontmatter::parse_yaml(&sidecar_contents)
+            .map_err(|e| e.in_file(Path::new(&sidecar)))?;
+
+        if let Some(sidecar_fm) = sidecar_fm.into_hash() {
+            fm.extend(sidecar_fm);
         }
-        false => Err(err),
     }
-}
 
-fn parse_frontmatter(frontmatter: &str) -> Result<Yaml, IoError> {
-    YamlLoader::load_from_str(frontmatter)
-        .map(|mut yaml_vec| yaml_vec.pop().unwrap())
-        .map_err(|_| IoError::new(ErrorKind::Other, "Failed to parse frontmatter as YAML."))
+    Ok(Yaml::Hash(fm))
 }
 
-fn dump_frontmatter(frontmatter: Yaml) -> TeraMap<String, TeraValue> {
-    let mut map = TeraMap::new();
-
-    let frontmatter_hashmap = match frontmatter.into_hash() {
-        Some(hm) => hm,
-        None => return map,
-    };
-
-    for (key, value) in &frontmatter_hashmap {
-        let key = key.as_str().unwrap().to_string();
+fn dump_frontmatter(frontmatter: Yaml) -> Result<TeraMap<String, TeraValue>, BuildError> {
+    match yaml_to_tera(&frontmatter, "frontmatter")? {
+        TeraValue::Object(map) => Ok(map),
+        _ => Err(BuildError::new(
+            "Frontmatter must be a mapping of keys to values.",
+        )),
+    }
+}
 
-        if let Some(value_as_vec) = value.as_vec() {
-            let stringified = value_as_vec
+/// Converts a frontmatter value, including nested arrays and hashes such as the list of author
+/// objects in a notebook's metadata. `path` names the value in errors, e.g. `frontmatter.tags[1]`.
+fn yaml_to_tera(value: &Yaml, path: &str) -> Result<TeraValue, BuildError> {
+    Ok(match value {
+        Yaml::String(s) => TeraValue::from(s.clone()),
+        Yaml::Integer(i) => TeraValue::from(*i),
+        Yaml::Real(_) => value.as_f64().map_or(TeraValue::Null, TeraValue::from),
+        Yaml::Boolean(b) => TeraValue::from(*b),
+        Yaml::Null => TeraValue::Null,
+        Yaml::Array(values) => TeraValue::Array(
+            values
                 .iter()
-                .map(|v| v.as_str().unwrap().to_string())
-                .collect::<Vec<String>>();
+                .enumerate()
+                .map(|(i, v)| yaml_to_tera(v, &format!("{}[{}]", path, i)))
+                .collect::<Result<Vec<TeraValue>, BuildError>>()?,
+        ),
+        Yaml::Hash(hash) => {
+            let mut map = TeraMap::new();
+
+            for (key, value) in hash {
+                let key = yaml_key(key).ok_or_else(|| {
+                    BuildError::new(format!(
+                        "Unsupported key in {}; keys must be strings, numbers or booleans.",
+                        path
+                    ))
+                })?;
+
+                let value = yaml_to_tera(value, &format!("{}.{}", path, key))?;
+                map.insert(key, value);
+            }
 
-            map.insert(key, TeraValue::from(stringified));
-        } else if let Some(value_as_str) = value.as_str() {
-            let stringified = value_as_str.to_string();
-            map.insert(key, TeraValue::from(stringified));
+            TeraValue::Object(map)
         }
-    }
+        Yaml::Alias(_) | Yaml::BadValue => {
+            return Err(BuildError::new(format!("Unsupported value for {}.", path)))
+        }
+    })
+}
 
-    map
+/// Hash keys are strings in templates; scalar keys such as `2020: ...` are stringified.
+fn yaml_key(key: &Yaml) -> Option<String> {
+    match key {
+        Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
 }
 
 fn build_templates() -> Tera {
-    let templates_path = SETTINGS.get_str("templates.path").unwrap();
-    let templates_glob = format!("{}/**/*", templates_path);
+    let settings = settings::current();
+    let templates_path = &settings.templates.path;
+    let templates_glob = format!("{}/**/*", templates_path.display());
 
     match Tera::new(&templates_glob) {
         Ok(t) => t,
         Err(e) => {
-            error!("Template error(s): {}", e);
+            error!("{}", template_error(&e, templates_path).diagnostic());
             process::exit(1);
         }
     }
 }
 
-fn render_pages(hashmap: HashMap<String, Page>) -> HashMap<String, Page> {
+/// Locates a template parse error from the `Failed to parse "<path>"` message Tera puts above the
+/// parser's `--> line:column` position.
+fn template_error(e: &tera::Error, templates_path: &Path) -> BuildError {
+    let mut error = BuildError::new("Failed to load templates.");
+    let mut cause: Option<&dyn Error> = Some(e);
+
+    while let Some(e) = cause {
+        let msg = e.to_string();
+
+        if let Some(path) = msg
+            .split("Failed to parse \"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+        {
+            error = BuildError::new(format!("Failed to parse template {}.", path))
+                .in_file(&templates_path.join(path));
+
+            if let Some(position) = msg.lines().find_map(|l| l.trim().strip_prefix("--> ")) {
+                let mut parts = position.split(':').map(|part| part.trim().parse().ok());
+
+                if let (Some(Some(line)), Some(column)) = (parts.next(), parts.next()) {
+                    error = error.at(line, column);
+                }
+            }
+
+            if let Some(reason) = msg.lines().rev().find(|l| l.trim_start().starts_with('=')) {
+                error = error.with_cause(reason.trim_start().trim_start_matches('=').trim());
+            }
+
+            return error;
+        }
+
+        cause = e.source();
+    }
+
+    error.with_cause(e)
+}
+
+fn render_pages(
+    hashmap: HashMap<String, Page>,
+    sections: &HashMap<String, Section>,
+    templates: &Tera,
+    diagnostics: &mut Diagnostics,
+) -> HashMap<String, Page> {
     let mut final_hashmap: HashMap<String, Page> = HashMap::new();
 
     let pages_vec = hashmap
         .clone()
         .into_iter()
         .map(|(_, page)| page)
+        .filter(|page| page.meta.layout.is_some())
         .collect::<Vec<Page>>();
 
-    let templates = build_templates();
+    let settings = settings::current();
     let mut context = TeraContext::new();
     context.insert("pages", &pages_vec);
-    context.insert("globals", &dump_globals());
+    context.insert("globals", &settings.templates.globals);
+
+    for (key, page) in hashmap.iter() {
+        let mut page_context = context.clone();
+        page_context.insert("breadcrumbs", &sections::breadcrumbs(page, &hashmap));
+
+        if let Some(section) = sections::section_context(page, sections, &hashmap) {
+            page_context.insert("section", &section);
+        }
 
-    for (key, page) in hashmap.into_iter() {
-        let rendered = match render_page(page.clone(), &templates, context.clone()) {
+        let rendered = match render_page(page.clone(), templates, page_context) {
             Ok(rendered) => rendered,
             Err(e) => {
-                error!("Failed to render {}: {:?}.", key, e);
+                diagnostics.report(e);
                 continue;
             }
         };
@@ -273,51 +465,51 @@ fn render_pages(hashmap: HashMap<String, Page>) -> HashMap<String, Page> {
         final_hashmap.insert(key.to_string(), final_page);
     }
 
-    let should_write_to_disk = SETTINGS.get_bool("write_to_disk").unwrap();
-
-    if should_write_to_disk {
+    if settings.write_to_disk {
         write_rendered_to_disk(&final_hashmap);
     }
 
     final_hashmap
 }
 
-fn dump_globals() -> TeraMap<String, TeraValue> {
-    let mut map = TeraMap::new();
-
-    let globals_hashmap = match SETTINGS.get_table("templates.globals") {
-        Ok(gh) => gh,
-        Err(_) => return map,
+fn render_page(
+    page: Page,
+    templates: &Tera,
+    mut context: TeraContext,
+) -> Result<String, BuildError> {
+    let layout = match &page.meta.layout {
+        Some(layout) => layout,
+        None => return Ok(page.content),
     };
 
-    for (key, value) in globals_hashmap.into_iter() {
-        if let Ok(value_as_bool) = value.clone().into_bool() {
-            map.insert(key, TeraValue::from(value_as_bool));
-        } else if let Ok(value_as_int) = value.clone().into_int() {
-            map.insert(key, TeraValue::from(value_as_int));
-        } else if let Ok(value_as_str) = value.into_str() {
-            let stringified = value_as_str.to_string();
-            map.insert(key, TeraValue::from(stringified));
-        }
+    if !templates.templates.contains_key(layout) {
+        return Err(layout_error(&page.meta.source, layout));
     }
 
-    map
-}
-
-fn render_page(page: Page, templates: &Tera, mut context: TeraContext) -> Result<String, IoError> {
     context.insert("page", &page);
 
-    let mut rendered = match templates.render(&page.meta.layout.unwrap(), &context) {
+    let mut rendered = match templates.render(layout, &context) {
         Ok(rendered) => rendered,
         Err(e) => {
+            let mut reasons = Vec::new();
             let mut cause = e.source();
 
             while let Some(e) = cause {
-                error!("Reason: {}", e);
+                reasons.push(e.to_string());
                 cause = e.source();
             }
 
-            return Err(IoError::new(ErrorKind::Other, e.to_string()));
+            let error = BuildError::new(format!(
+                "Failed to render {} with layout '{}'.",
+                page.meta.source.display(),
+                layout
+            ));
+
+            return Err(template_render_error(
+                error,
+                &templates.templates[layout],
+                &reasons,
+            ));
         }
     };
 
@@ -326,7 +518,9 @@ fn render_page(page: Page, templates: &Tera, mut context: TeraContext) -> Result
             let mut minifier = HTMLMinifier::new();
 
             if let Err(e) = minifier.digest(rendered) {
-                return Err(IoError::new(ErrorKind::Other, e.to_string()));
+                return Err(BuildError::new("Failed to minify the rendered page.")
+                    .in_file(&page.meta.source)
+                    .with_cause(e));
             };
 
             rendered = minifier.get_html();
@@ -337,6 +531,47 @@ fn render_page(page: Page, templates: &Tera, mut context: TeraContext) -> Result
     Ok(rendered)
 }
 
+/// Tera doesn't report where rendering failed, so this points at the first line of the layout
+/// mentioning the `name` its error message quotes, if any.
+fn template_render_error(error: BuildError, template: &Template, reasons: &[String]) -> BuildError {
+    let error = match reasons.is_empty() {
+        true => error,
+        false => error.with_cause(reasons.join(": ")),
+    };
+
+    let path = match &template.path {
+        Some(path) => Path::new(path),
+        None => return error,
+    };
+
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    let name = reasons
+        .iter()
+        .find_map(|reason| reason.split('`').nth(1))
+        .filter(|name| !name.is_empty());
+
+    match name.and_then(|name| contents.find(name)) {
+        Some(offset) => error.in_file(path).at_offset(&contents, offset, 0),
+        None => error.in_file(path),
+    }
+}
+
+/// Points at the `layout` frontmatter key when the unknown layout was set explicitly rather than
+/// being the page type's default.
+fn layout_error(source: &Path, layout: &str) -> BuildError {
+    let error = BuildError::new(format!("Unknown layout '{}'.", layout)).in_file(source);
+    let contents = fs::read_to_string(source).unwrap_or_default();
+    let key = contents.lines().position(|line| {
+        let line = line.trim_start().trim_start_matches('"');
+        line.starts_with("layout") && line.contains(layout)
+    });
+
+    match key {
+        Some(index) => error.at(index + 1, None),
+        None => error.with_cause("the page type's default_layout has no template"),
+    }
+}
+
 fn write_rendered_to_disk(hashmap: &HashMap<String, Page>) {
     let mut rendered_path = env::current_dir().unwrap();
     rendered_path.push(".rendered");
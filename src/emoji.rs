@@ -0,0 +1,148 @@
+/// GitHub-style `:shortcode:` names of commonly used emoji.
+const EMOJI: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("alarm_clock", "⏰"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("bangbang", "‼️"),
+    ("beer", "🍺"),
+    ("bell", "🔔"),
+    ("blush", "😊"),
+    ("bomb", "💣"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("calendar", "📆"),
+    ("camera", "📷"),
+    ("cat", "🐱"),
+    ("chart_with_upwards_trend", "📈"),
+    ("check", "✔️"),
+    ("checkered_flag", "🏁"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("closed_lock_with_key", "🔐"),
+    ("cloud", "☁️"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("cool", "🆒"),
+    ("crab", "🦀"),
+    ("cry", "😢"),
+    ("dog", "🐶"),
+    ("exclamation", "❗"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("gear", "⚙️"),
+    ("gift", "🎁"),
+    ("globe_with_meridians", "🌐"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("hammer_and_wrench", "🛠️"),
+    ("heart", "❤️"),
+    ("heavy_check_mark", "✔️"),
+    ("heavy_minus_sign", "➖"),
+    ("heavy_plus_sign", "➕"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("information_source", "ℹ️"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("laughing", "😆"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("memo", "📝"),
+    ("moon", "🌙"),
+    ("muscle", "💪"),
+    ("no_entry", "⛔"),
+    ("ok_hand", "👌"),
+    ("package", "📦"),
+    ("pencil", "📝"),
+    ("pencil2", "✏️"),
+    ("point_right", "👉"),
+    ("pray", "🙏"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("rocket", "🚀"),
+    ("rotating_light", "🚨"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("shrug", "🤷"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("snake", "🐍"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("stopwatch", "⏱️"),
+    ("sunglasses", "😎"),
+    ("sunny", "☀️"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("trophy", "🏆"),
+    ("unlock", "🔓"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+];
+
+pub fn lookup(shortcode: &str) -> Option<&'static str> {
+    EMOJI
+        .binary_search_by(|(name, _)| name.cmp(&shortcode))
+        .ok()
+        .map(|i| EMOJI[i].1)
+}
+
+/// Replaces known `:shortcode:` names in `text`; unknown ones, and colons in times such as
+/// `10:30:00`, are left as is.
+pub fn replace(text: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(':') {
+        replaced += &rest[..start];
+        rest = &rest[start..];
+
+        let name_len = rest[1..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-'))
+            .unwrap_or(rest.len() - 1);
+
+        match rest[1 + name_len..].starts_with(':') {
+            true if name_len > 0 => match lookup(&rest[1..1 + name_len]) {
+                Some(emoji) => {
+                    replaced += emoji;
+                    rest = &rest[name_len + 2..];
+                }
+                None => {
+                    replaced += &rest[..1 + name_len];
+                    rest = &rest[1 + name_len..];
+                }
+            },
+            _ => {
+                replaced += &rest[..1 + name_len];
+                rest = &rest[1 + name_len..];
+            }
+        }
+    }
+
+    replaced += rest;
+    replaced
+}
@@ -0,0 +1,224 @@
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+
+/// An error found while building the site, located in its source file when possible.
+#[derive(Debug, Serialize)]
+pub struct BuildError {
+    pub message: String,
+    pub path: Option<PathBuf>,
+    /// 1-based line and column.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub cause: Option<String>,
+}
+
+impl BuildError {
+    pub fn new<S: Into<String>>(message: S) -> BuildError {
+        BuildError {
+            message: message.into(),
+            path: None,
+            line: None,
+            column: None,
+            cause: None,
+        }
+    }
+
+    /// Sets the file the error is in, unless a more precise one is already known.
+    pub fn in_file(mut self, path: &Path) -> BuildError {
+        if self.path.is_none() {
+            self.path = Some(path.to_path_buf());
+        }
+
+        self
+    }
+
+    /// Locates the error at `line` and `column`. Parsers such as serde_json report column 0 when
+    /// the position has none, e.g. at the end of the input, so it's treated as no column.
+    pub fn at(mut self, line: usize, column: Option<usize>) -> BuildError {
+        self.line = Some(line);
+        self.column = column.filter(|&column| column > 0);
+        self
+    }
+
+    /// Locates the error at byte `offset` of `source`, which starts `line_offset` lines into its
+    /// file.
+    pub fn at_offset(self, source: &str, offset: usize, line_offset: usize) -> BuildError {
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count() + 1;
+
+        self.at(line_offset + before.matches('\n').count() + 1, Some(column))
+    }
+
+    /// Locates the error at the first mention of `needle` in the file at `path`, or at the file if
+    /// there's none.
+    pub fn at_mention(self, path: &Path, needle: &str) -> BuildError {
+        let error = self.in_file(path);
+        let contents = fs::read_to_string(path).unwrap_or_default();
+
+        match contents.find(needle) {
+            Some(offset) if !needle.is_empty() => error.at_offset(&contents, offset, 0),
+            _ => error,
+        }
+    }
+
+    /// Moves the error `lines` lines down, for errors found in an excerpt of a file.
+    pub fn shifted(mut self, lines: usize) -> BuildError {
+        self.line = self.line.map(|line| line + lines);
+        self
+    }
+
+    pub fn with_cause<C: fmt::Display>(mut self, cause: C) -> BuildError {
+        self.cause = Some(cause.to_string());
+        self
+    }
+
+    fn location(&self) -> Option<String> {
+        let path = self.path.as_ref()?;
+
+        Some(match (self.line, self.column) {
+            (Some(line), Some(column)) => format!("{}:{}:{}", path.display(), line, column),
+            (Some(line), None) => format!("{}:{}", path.display(), line),
+            _ => path.display().to_string(),
+        })
+    }
+
+    /// Renders the error as a multi-line diagnostic, with a frame of the source lines around
+    /// it when its line is known.
+    pub fn diagnostic(&self) -> String {
+        let mut diagnostic = self.message.clone();
+
+        if let Some(location) = self.location() {
+            diagnostic += &format!("\n  --> {}", location);
+        }
+
+        if let (Some(path), Some(line)) = (&self.path, self.line) {
+            if let Ok(source) = fs::read_to_string(path) {
+                diagnostic += &code_frame(&source, line, self.column);
+            }
+        }
+
+        if let Some(cause) = &self.cause {
+            diagnostic += &format!("\n   = cause: {}", cause);
+        }
+
+        diagnostic
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(location) = self.location() {
+            write!(f, "{}: ", location)?;
+        }
+
+        write!(f, "{}", self.message)?;
+
+        match &self.cause {
+            Some(cause) => write!(f, " ({})", cause),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<IoError> for BuildError {
+    fn from(e: IoError) -> BuildError {
+        BuildError::new(e.to_string())
+    }
+}
+
+/// Shows `line` of `source` between its neighbours, with a caret under `column`.
+fn code_frame(source: &str, line: usize, column: Option<usize>) -> String {
+    let lines = source.lines().collect::<Vec<&str>>();
+
+    if line == 0 || line > lines.len() + 1 {
+        return String::new();
+    }
+
+    let first = line.saturating_sub(1).max(1);
+    let last = (line + 1).min(lines.len());
+    let width = last.max(line).to_string().len();
+    let mut frame = format!("\n{:width$} |", "", width = width);
+
+    for nb in first..=last.max(line) {
+        let text = lines.get(nb - 1).unwrap_or(&"");
+        frame += &format!("\n{:>width$} |", nb, width = width);
+
+        if !text.is_empty() {
+            frame += &format!(" {}", text);
+        }
+
+        if let (true, Some(column)) = (nb == line, column) {
+            // Keep tabs so the caret lines up with the text above it.
+            let indent = text
+                .chars()
+                .take(column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+
+            frame += &format!("\n{:width$} | {}^", "", indent, width = width);
+        }
+    }
+
+    frame
+}
+
+/// Drops the ` at line N column M` suffix parsers add to their messages, once the position is
+/// reported separately.
+pub fn without_position(message: &str) -> &str {
+    match message.rfind(" at line ") {
+        Some(i) => &message[..i],
+        None => message,
+    }
+}
+
+/// Collects the errors and warnings of a build, reporting each as it's found and all of them in a
+/// summary at the end.
+#[derive(Default, Serialize)]
+pub struct Diagnostics {
+    pub errors: Vec<BuildError>,
+    pub warnings: Vec<BuildError>,
+}
+
+impl Diagnostics {
+    pub fn report(&mut self, error: BuildError) {
+        error!("{}", error.diagnostic());
+        self.errors.push(error);
+    }
+
+    pub fn warn(&mut self, warning: BuildError) {
+        warn!("{}", warning.diagnostic());
+        self.warnings.push(warning);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn summary(&self) {
+        let list = self
+            .errors
+            .iter()
+            .map(|e| format!("\n  - error: {}", e))
+            .chain(
+                self.warnings
+                    .iter()
+                    .map(|w| format!("\n  - warning: {}", w)),
+            )
+            .collect::<String>();
+
+        match (self.errors.len(), self.warnings.len()) {
+            (0, 0) => info!("Build finished without errors."),
+            (0, warnings) => warn!("Build finished with {} warning(s):{}", warnings, list),
+            (errors, warnings) => error!(
+                "Build finished with {} error(s) and {} warning(s):{}",
+                errors, warnings, list
+            ),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::errors::BuildError;
+use crate::markdown::{RenderOptions, Rendered};
+use yaml_rust::Yaml;
+
+mod asciidoc;
+mod csv;
+mod djot;
+mod notebook;
+mod org;
+
+/// How a content file is turned into a page's content, keyed by file extension in `REGISTRY`.
+pub enum Format {
+    /// Lightweight markup translated to Markdown, then rendered by `markdown::from`.
+    Markup(fn(&str) -> String),
+    /// Structured files that can't carry frontmatter. `metadata` extracts frontmatter keys from
+    /// the file, overridden by a `<file>.yml` sidecar, and `render` renders it to HTML.
+    Document {
+        metadata: fn(&str) -> Result<Yaml, BuildError>,
+        render: fn(&str, &Yaml, &RenderOptions) -> Result<Rendered, BuildError>,
+    },
+    /// HTML, passed through as is.
+    Html,
+    /// Other text files, passed through as is.
+    Raw,
+}
+
+const REGISTRY: &[(&str, Format)] = &[
+    ("md", Format::Markup(markdown)),
+    ("djot", Format::Markup(djot::to_markdown)),
+    ("dj", Format::Markup(djot::to_markdown)),
+    ("org", Format::Markup(org::to_markdown)),
+    ("adoc", Format::Markup(asciidoc::to_markdown)),
+    ("asciidoc", Format::Markup(asciidoc::to_markdown)),
+    (
+        "ipynb",
+        Format::Document {
+            metadata: notebook::metadata,
+            render: notebook::render,
+        },
+    ),
+    (
+        "csv",
+        Format::Document {
+            metadata: csv::metadata,
+            render: csv::render,
+        },
+    ),
+    ("html", Format::Html),
+    ("css", Format::Raw),
+    ("js", Format::Raw),
+    ("json", Format::Raw),
+    ("xml", Format::Raw),
+    ("txt", Format::Raw),
+];
+
+pub fn lookup(extension: &str) -> Option<&'static Format> {
+    REGISTRY
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, format)| format)
+}
+
+/// Whether files with this extension are rendered to HTML pages, served without the extension.
+pub fn is_page(extension: &str) -> bool {
+    matches!(
+        lookup(extension),
+        Some(Format::Markup(_)) | Some(Format::Document { .. }) | Some(Format::Html)
+    )
+}
+
+pub fn is_markup(extension: &str) -> bool {
+    matches!(lookup(extension), Some(Format::Markup(_)))
+}
+
+fn markdown(content: &str) -> String {
+    content.to_string()
+}
+
+/// Applies `f` to the parts of `line` outside inline code spans.
+fn map_text<F>(line: &str, f: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let mut mapped = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find('`') {
+        let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let delimiter = &rest[start..start + ticks];
+        let end = match rest[start + ticks..].find(delimiter) {
+            Some(i) => start + ticks + i + ticks,
+            None => break,
+        };
+
+        mapped += &f(&rest[..start]);
+        mapped += &rest[start..end];
+        rest = &rest[end..];
+    }
+
+    mapped += &f(rest);
+    mapped
+}
+
+/// Replaces spans such as `*strong*` delimited by `open` and `close` with `wrap(inner)`. The
+/// delimiters only count at word boundaries, so `2*3*4` and `snake_case_name` are left alone.
+fn replace_spans<F>(text: &str, open: &str, close: &str, wrap: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let mut replaced = String::with_capacity(text.len());
+    let mut pos = 0;
+
+    while pos < text.len() {
+        let rest = &text[pos..];
+        let at_boundary = text[..pos]
+            .chars()
+            .last()
+            .is_none_or(|c| c.is_whitespace() || "([{\"'".contains(c));
+
+        if at_boundary && rest.starts_with(open) {
+            let inner_start = pos + open.len();
+
+            if let Some(inner_len) = find_close(&text[inner_start..], close) {
+                replaced += &wrap(&text[inner_start..inner_start + inner_len]);
+                pos = inner_start + inner_len + close.len();
+                continue;
+            }
+        }
+
+        let c = rest.chars().next().unwrap();
+        replaced.push(c);
+        pos += c.len_utf8();
+    }
+
+    replaced
+}
+
+fn find_close(text: &str, close: &str) -> Option<usize> {
+    if text.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    text.match_indices(close).map(|(i, _)| i).find(|&i| {
+        let after = &text[i + close.len()..];
+
+        i > 0
+            && !text[..i].ends_with(char::is_whitespace)
+            && after
+                .chars()
+                .next()
+                .is_none_or(|c| c.is_whitespace() || ".,;:!?)]}\"'-".contains(c))
+    })
+}
+
+/// Like `replace_spans`, for delimiters that may appear inside words such as `H~2~O`.
+/// Single-character delimiters can't enclose whitespace.
+fn replace_pairs<F>(text: &str, open: &str, close: &str, wrap: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(open) {
+        let inner = &rest[start + open.len()..];
+        let inner_len = match inner.find(close) {
+            Some(len) if len > 0 && !inner[..len].contains(char::is_whitespace) => Some(len),
+            Some(len) if len > 0 && open.len() > 1 => Some(len),
+            _ => None,
+        };
+
+        match inner_len {
+            Some(len) => {
+                replaced += &rest[..start];
+                replaced += &wrap(&inner[..len]);
+                rest = &inner[len + close.len()..];
+            }
+            None => {
+                replaced += &rest[..start + open.len()];
+                rest = inner;
+            }
+        }
+    }
+
+    replaced += rest;
+    replaced
+}
@@ -0,0 +1,247 @@
+use super::{map_text, replace_pairs, replace_spans};
+
+/// Translates a subset of AsciiDoc to Markdown: `=` section titles, `*` and `.` lists,
+/// `[source,lang]` listing blocks, `....` literal blocks, `____` quote blocks, admonitions,
+/// `|===` tables, `*bold*`, `_italic_`, `#highlight#`, `link:`, `xref:`, `<<id>>` and `image:`
+/// macros. Document attributes and comments are dropped.
+pub fn to_markdown(content: &str) -> String {
+    let mut markdown = String::with_capacity(content.len());
+    let mut lang: Option<String> = None;
+    let mut admonition: Option<String> = None;
+    let mut delimiter: Option<&str> = None;
+    let mut open_block: Option<bool> = None;
+    let mut in_quote = false;
+    let mut in_comment = false;
+    let mut table_row = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim_end();
+
+        if in_comment {
+            in_comment = trimmed != "////";
+            markdown += "\n";
+            continue;
+        }
+
+        if let Some(d) = delimiter {
+            match trimmed == d {
+                true => {
+                    delimiter = None;
+                    markdown += "```\n";
+                }
+                false => {
+                    markdown += line;
+                    markdown += "\n";
+                }
+            }
+
+            continue;
+        }
+
+        if let Some(ref mut row) = table_row {
+            if trimmed == "|===" {
+                table_row = None;
+                markdown += "\n";
+            } else if let Some(cells) = trimmed.strip_prefix('|') {
+                markdown += &format!("| {} |\n", inline(cells));
+
+                if *row == 0 {
+                    let columns = cells.split('|').count();
+                    markdown += &format!("|{}\n", "---|".repeat(columns));
+                }
+
+                *row += 1;
+            } else {
+                markdown += &inline(trimmed);
+                markdown += "\n";
+            }
+
+            continue;
+        }
+
+        if let Some(attributes) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let mut parts = attributes.split(',').map(str::trim);
+
+            match parts.next() {
+                Some("source") => lang = Some(parts.next().unwrap_or("").to_string()),
+                Some(kind) if is_admonition(kind) => admonition = Some(kind.to_lowercase()),
+                _ => {}
+            }
+
+            markdown += "\n";
+        } else if trimmed == "----" || trimmed == "...." {
+            delimiter = Some(if trimmed == "----" { "----" } else { "...." });
+            markdown += &format!("```{}\n", lang.take().unwrap_or_default());
+        } else if trimmed == "====" || trimmed == "****" {
+            match (open_block.take(), admonition.take()) {
+                (Some(true), _) => markdown += ":::\n",
+                (Some(false), _) => markdown += "\n",
+                (None, Some(kind)) => {
+                    open_block = Some(true);
+                    markdown += &format!(":::{}\n", kind);
+                }
+                (None, None) => {
+                    open_block = Some(false);
+                    markdown += "\n";
+                }
+            }
+        } else if trimmed == "____" {
+            in_quote = !in_quote;
+            markdown += "\n";
+        } else if trimmed == "////" {
+            in_comment = true;
+            markdown += "\n";
+        } else if trimmed == "|===" {
+            table_row = Some(0);
+            markdown += "\n";
+        } else if trimmed.starts_with("//") || is_attribute_entry(trimmed) || trimmed == "+" {
+            markdown += "\n";
+        } else if trimmed == "'''" {
+            markdown += "---\n";
+        } else if in_quote {
+            markdown += &format!("> {}\n", inline(trimmed));
+        } else {
+            markdown += &block_line(trimmed);
+            markdown += "\n";
+        }
+    }
+
+    markdown
+}
+
+fn block_line(line: &str) -> String {
+    let marker_len = |c: char| line.len() - line.trim_start_matches(c).len();
+
+    let level = marker_len('=');
+
+    if level > 0 && line[level..].starts_with(' ') {
+        return format!("{}{}", "#".repeat(level.min(6)), inline(&line[level..]));
+    }
+
+    for c in ['*', '.'] {
+        let depth = marker_len(c);
+
+        if depth > 0 && line[depth..].starts_with(' ') && !(c == '.' && depth > 5) {
+            let marker = if c == '*' { "-" } else { "1." };
+            let indent = "   ".repeat(depth - 1);
+            return format!("{}{}{}", indent, marker, inline(&line[depth..]));
+        }
+    }
+
+    if let Some((kind, text)) = line.split_once(": ") {
+        if is_admonition(kind) {
+            return format!("> [!{}]\n> {}", kind, inline(text));
+        }
+    }
+
+    if let Some(image) = line.strip_prefix("image::") {
+        return image_macro(image);
+    }
+
+    inline(line)
+}
+
+fn is_admonition(kind: &str) -> bool {
+    ["NOTE", "TIP", "IMPORTANT", "CAUTION", "WARNING"].contains(&kind)
+}
+
+/// `:name: value` document attribute entries.
+fn is_attribute_entry(line: &str) -> bool {
+    match line.strip_prefix(':').and_then(|l| l.find(':')) {
+        Some(i) => i > 0 && !line[1..=i].contains(' '),
+        None => false,
+    }
+}
+
+fn inline(line: &str) -> String {
+    map_text(line, |text| {
+        let text = macros(text);
+        let text = replace_spans(&text, "*", "*", |s| format!("**{}**", s));
+        let text = replace_spans(&text, "#", "#", |s| format!("<mark>{}</mark>", s));
+        let text = replace_pairs(&text, "^", "^", |s| format!("<sup>{}</sup>", s));
+        replace_pairs(&text, "~", "~", |s| format!("<sub>{}</sub>", s))
+    })
+}
+
+/// Rewrites `link:url[text]`, `https://url[text]`, `xref:file.adoc[text]`, `<<id,text>>` and
+/// `image:path[alt]` macros as Markdown links and images.
+fn macros(text: &str) -> String {
+    let mut rewritten = String::with_capacity(text.len());
+    let mut rest = text;
+
+    loop {
+        let next = ["link:", "xref:", "image:", "http://", "https://", "<<"]
+            .iter()
+            .filter_map(|prefix| rest.find(prefix).map(|i| (i, *prefix)))
+            .min();
+
+        let (start, prefix) = match next {
+            Some(next) => next,
+            None => break,
+        };
+
+        rewritten += &rest[..start];
+        rest = &rest[start..];
+
+        if prefix == "<<" {
+            match rest.find(">>") {
+                Some(end) => {
+                    let inner = &rest[2..end];
+                    let (id, label) = inner.split_once(',').unwrap_or((inner, inner));
+                    rewritten += &format!("[{}](#{})", label.trim(), id.trim());
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    rewritten += "<<";
+                    rest = &rest[2..];
+                }
+            }
+
+            continue;
+        }
+
+        let target_start = match prefix {
+            "http://" | "https://" => 0,
+            prefix => prefix.len(),
+        };
+
+        let bracket = rest
+            .find('[')
+            .filter(|&i| i > target_start && !rest[target_start..i].contains(char::is_whitespace));
+
+        let end = bracket.and_then(|i| rest[i..].find(']').map(|end| i + end));
+
+        match (bracket, end) {
+            (Some(bracket), Some(end)) => {
+                let target = &rest[target_start..bracket];
+                let label = &rest[bracket + 1..end];
+
+                rewritten += &match prefix {
+                    "image:" => format!("![{}]({})", label, target),
+                    _ if label.is_empty() => format!("<{}>", target),
+                    _ => format!("[{}]({})", label, target),
+                };
+
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                rewritten += &rest[..prefix.len()];
+                rest = &rest[prefix.len()..];
+            }
+        }
+    }
+
+    rewritten += rest;
+    rewritten
+}
+
+fn image_macro(image: &str) -> String {
+    match image.find('[') {
+        Some(i) => format!(
+            "![{}]({})",
+            image[i + 1..].trim_end_matches(']'),
+            &image[..i]
+        ),
+        None => image.to_string(),
+    }
+}
@@ -0,0 +1,191 @@
+use crate::errors::BuildError;
+use crate::markdown::{RenderOptions, Rendered};
+use tera::escape_html;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::Yaml;
+
+/// Sorts the rows of `table.sortable` when a column header is clicked, numerically when both
+/// cells are numbers.
+const SORT_SCRIPT: &str = r#"<script>
+document.querySelectorAll("table.sortable").forEach(function (table) {
+  table.querySelectorAll("th button").forEach(function (button, column) {
+    button.addEventListener("click", function () {
+      var th = button.parentNode;
+      var ascending = th.getAttribute("aria-sort") !== "ascending";
+      var body = table.tBodies[0];
+
+      table.querySelectorAll("th").forEach(function (header) {
+        header.removeAttribute("aria-sort");
+      });
+      th.setAttribute("aria-sort", ascending ? "ascending" : "descending");
+
+      Array.from(body.rows)
+        .sort(function (x, y) {
+          var a = x.cells[column].textContent;
+          var b = y.cells[column].textContent;
+          var difference = parseFloat(a) - parseFloat(b);
+          var order = isNaN(difference) ? a.localeCompare(b) : difference;
+          return ascending ? order : -order;
+        })
+        .forEach(function (row) {
+          body.appendChild(row);
+        });
+    });
+  });
+});
+</script>"#;
+
+/// CSV files carry no metadata of their own; it comes from the sidecar file.
+pub fn metadata(_content: &str) -> Result<Yaml, BuildError> {
+    Ok(Yaml::Hash(YamlHash::new()))
+}
+
+/// Renders a CSV file as a table whose columns can be sorted by clicking their header. The
+/// `delimiter` (default `,`), `header` (default `true`) and `caption` frontmatter keys configure
+/// the table.
+pub fn render(content: &str, fm: &Yaml, _opts: &RenderOptions) -> Result<Rendered, BuildError> {
+    let delimiter = match fm["delimiter"].as_str() {
+        Some(delimiter) if delimiter.chars().count() == 1 => delimiter.chars().next().unwrap(),
+        Some(delimiter) => {
+            return Err(BuildError::new(format!(
+                "CSV delimiter '{}' must be a single character.",
+                delimiter
+            )))
+        }
+        None => ',',
+    };
+
+    let mut records = parse(content, delimiter)?.into_iter();
+    let has_header = fm["header"].as_bool().unwrap_or(true);
+    let mut html = String::from("<table class=\"csv-table sortable\">");
+
+    if let Some(caption) = fm["caption"].as_str() {
+        html += &format!("<caption>{}</caption>", escape_html(caption));
+    }
+
+    if has_header {
+        if let Some(header) = records.next() {
+            html += "<thead><tr>";
+
+            for cell in header {
+                html += &format!(
+                    "<th scope=\"col\"><button type=\"button\">{}</button></th>",
+                    escape_html(&cell)
+                );
+            }
+
+            html += "</tr></thead>";
+        }
+    }
+
+    html += "<tbody>";
+
+    for record in records {
+        html += "<tr>";
+
+        for cell in record {
+            html += &format!("<td>{}</td>", escape_html(&cell).replace('\n', "<br>"));
+        }
+
+        html += "</tr>";
+    }
+
+    html += "</tbody></table>";
+
+    if has_header {
+        html += SORT_SCRIPT;
+    }
+
+    Ok(Rendered {
+        html,
+        summary: None,
+        toc: Vec::new(),
+        dependencies: Vec::new(),
+    })
+}
+
+/// Parses RFC 4180 records: fields may be quoted, with `""` escaping a quote, and quoted fields
+/// may span lines.
+fn parse(content: &str, delimiter: char) -> Result<Vec<Vec<String>>, BuildError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line_nb = 1;
+    let mut quote_line_nb = 1;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if in_quotes => in_quotes = false,
+            '"' if field.is_empty() => {
+                in_quotes = true;
+                quote_line_nb = line_nb;
+            }
+            '\n' if in_quotes => {
+                field.push('\n');
+                line_nb += 1;
+            }
+            c if c == delimiter && !in_quotes => record.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes && chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line_nb += 1;
+            }
+            c => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err(BuildError::new("Unterminated quoted CSV field.").at(quote_line_nb, None));
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_fields_keep_delimiters_quotes_and_newlines() {
+        let records = parse(
+            "name,note\n\"Doe, J\",\"said \"\"hi\"\"\nthen left\"\n",
+            ',',
+        )
+        .unwrap();
+
+        assert_eq!(
+            records,
+            [
+                vec!["name", "note"],
+                vec!["Doe, J", "said \"hi\"\nthen left"],
+            ]
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings_end_records() {
+        let records = parse("a;b\r\n1;2\r\n3;", ';').unwrap();
+
+        assert_eq!(records, [vec!["a", "b"], vec!["1", "2"], vec!["3", ""]]);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_located() {
+        let error = parse("a,b\n1,\"2\n3,4\n", ',').unwrap_err();
+
+        assert_eq!(error.message, "Unterminated quoted CSV field.");
+        assert_eq!(error.line, Some(2));
+    }
+}
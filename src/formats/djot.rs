@@ -0,0 +1,116 @@
+use super::{map_text, replace_pairs, replace_spans};
+
+/// Translates Djot to Markdown. Headings, lists, links, code blocks, footnotes and `:::` divs
+/// share their syntax; `*strong*`, `{=mark=}`, `{+insert+}`, `{-delete-}`, `^sup^`, `~sub~`,
+/// math and `=html` raw blocks are rewritten, and attributes are dropped.
+pub fn to_markdown(content: &str) -> String {
+    let mut markdown = String::with_capacity(content.len());
+    let mut fence: Option<String> = None;
+    let mut raw = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if let Some(ref f) = fence {
+            if trimmed.starts_with(f.as_str()) && trimmed.trim_start_matches('`').is_empty() {
+                fence = None;
+
+                if raw {
+                    raw = false;
+                    markdown += "\n";
+                    continue;
+                }
+            }
+
+            markdown += line;
+            markdown += "\n";
+            continue;
+        }
+
+        if trimmed.starts_with("```") {
+            let ticks = trimmed.len() - trimmed.trim_start_matches('`').len();
+            fence = Some(trimmed[..ticks].to_string());
+
+            if trimmed[ticks..].trim().starts_with('=') {
+                raw = true;
+                markdown += "\n";
+                continue;
+            }
+
+            markdown += line;
+            markdown += "\n";
+            continue;
+        }
+
+        if trimmed.starts_with('{') && trimmed.ends_with('}') {
+            markdown += "\n";
+            continue;
+        }
+
+        markdown += &inline(line);
+        markdown += "\n";
+    }
+
+    markdown
+}
+
+fn inline(line: &str) -> String {
+    let line = math(line);
+
+    map_text(&line, |text| {
+        let text = strip_attributes(text);
+        let text = replace_spans(&text, "*", "*", |s| format!("**{}**", s));
+        let text = replace_pairs(&text, "{=", "=}", |s| format!("<mark>{}</mark>", s));
+        let text = replace_pairs(&text, "{+", "+}", |s| format!("<ins>{}</ins>", s));
+        let text = replace_pairs(&text, "{-", "-}", |s| format!("<del>{}</del>", s));
+        let text = replace_pairs(&text, "^", "^", |s| format!("<sup>{}</sup>", s));
+        replace_pairs(&text, "~", "~", |s| format!("<sub>{}</sub>", s))
+    })
+}
+
+/// Rewrites ``$`inline` `` and ``$$`display` `` math to `$inline$` and `$$display$$`.
+fn math(line: &str) -> String {
+    let mut rewritten = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find("$`") {
+        let dollars = if rest[..start].ends_with('$') { 2 } else { 1 };
+        let end = match rest[start + 2..].find('`') {
+            Some(end) => start + 2 + end,
+            None => break,
+        };
+
+        rewritten += &rest[..start + 1 - dollars];
+        rewritten += &"$".repeat(dollars);
+        rewritten += &rest[start + 2..end];
+        rewritten += &"$".repeat(dollars);
+        rest = &rest[end + 1..];
+    }
+
+    rewritten += rest;
+    rewritten
+}
+
+/// Drops inline attributes such as `{.class #id}`.
+fn strip_attributes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        let is_attribute = rest[start + 1..].starts_with(['.', '#']);
+
+        match rest[start..].find('}') {
+            Some(len) if is_attribute => {
+                stripped += &rest[..start];
+                rest = &rest[start + len + 1..];
+            }
+            _ => {
+                stripped += &rest[..start + 1];
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+
+    stripped += rest;
+    stripped
+}
@@ -0,0 +1,226 @@
+use crate::errors::{self, BuildError};
+use crate::frontmatter::json_to_yaml;
+use crate::markdown::{self, RenderOptions, Rendered};
+use serde_json::Value as JsonValue;
+use tera::escape_html;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::Yaml;
+
+/// Frontmatter of a Jupyter notebook: the notebook's `title` and `authors` metadata, overridden
+/// by the keys of its `thea` metadata object.
+pub fn metadata(content: &str) -> Result<Yaml, BuildError> {
+    let notebook = parse(content)?;
+    let metadata = &notebook["metadata"];
+    let mut fm = YamlHash::new();
+
+    for key in &["title", "authors"] {
+        if !metadata[key].is_null() {
+            fm.insert(Yaml::String(key.to_string()), json_to_yaml(&metadata[key]));
+        }
+    }
+
+    if let Some(thea) = metadata["thea"].as_object() {
+        for (key, value) in thea {
+            fm.insert(Yaml::String(key.clone()), json_to_yaml(value));
+        }
+    }
+
+    Ok(Yaml::Hash(fm))
+}
+
+/// Renders a notebook as a single Markdown document so that headings, the table of contents and
+/// the summary span every cell: Markdown cells are kept as is, code cells become fenced code
+/// blocks in the kernel's language and outputs are spliced into the HTML afterwards.
+pub fn render(content: &str, _fm: &Yaml, opts: &RenderOptions) -> Result<Rendered, BuildError> {
+    let notebook = parse(content)?;
+    let metadata = &notebook["metadata"];
+    let lang = metadata["language_info"]["name"]
+        .as_str()
+        .or_else(|| metadata["kernelspec"]["language"].as_str())
+        .unwrap_or("python");
+
+    let mut document = String::new();
+    let mut outputs = Vec::new();
+    let empty = Vec::new();
+
+    for cell in notebook["cells"].as_array().unwrap_or(&empty) {
+        let source = text(&cell["source"]);
+
+        match cell["cell_type"].as_str() {
+            Some("markdown") => {
+                document += &source;
+                document += "\n\n";
+            }
+            Some("code") => {
+                let fence = "`".repeat(longest_backtick_run(&source).max(2) + 1);
+                document += &format!("{}{}\n{}\n{}\n\n", fence, lang, source, fence);
+
+                let cell_outputs = cell["outputs"].as_array().unwrap_or(&empty);
+                let html = cell_outputs.iter().map(render_output).collect::<String>();
+
+                if !html.is_empty() {
+                    document += &format!("{}</div>\n\n", output_tag(outputs.len()));
+                    outputs.push(html);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Lines of the generated document don't match those of the notebook.
+    let mut rendered = markdown::from(&document, opts).map_err(|mut e| {
+        e.line = None;
+        e.column = None;
+        e
+    })?;
+    rendered.html = splice_outputs(&rendered.html, &outputs);
+    rendered.summary = rendered.summary.map(|s| splice_outputs(&s, &outputs));
+
+    Ok(rendered)
+}
+
+fn parse(content: &str) -> Result<JsonValue, BuildError> {
+    serde_json::from_str(content).map_err(|e| {
+        BuildError::new("Failed to parse notebook as JSON.")
+            .with_cause(errors::without_position(&e.to_string()))
+            .at(e.line(), Some(e.column()))
+    })
+}
+
+/// Notebook text fields are either a string or an array of lines.
+fn text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Array(lines) => lines.iter().filter_map(|l| l.as_str()).collect(),
+        JsonValue::String(s) => s.clone(),
+        _ => String::new(),
+    }
+}
+
+fn longest_backtick_run(source: &str) -> usize {
+    source.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+fn output_tag(index: usize) -> String {
+    format!("<div class=\"nb-output\" data-output=\"{}\">", index)
+}
+
+/// Fills the empty output `<div>`s left in the Markdown with the rendered outputs.
+fn splice_outputs(html: &str, outputs: &[String]) -> String {
+    let mut html = html.to_string();
+
+    for (i, output) in outputs.iter().enumerate() {
+        let tag = output_tag(i);
+        html = html.replace(
+            &format!("{}</div>", tag),
+            &format!("{}{}</div>", tag, output),
+        );
+    }
+
+    html
+}
+
+/// Renders a stream, result, display or error output, preferring HTML, then images, then text.
+fn render_output(output: &JsonValue) -> String {
+    match output["output_type"].as_str() {
+        Some("stream") => format!(
+            "<pre class=\"nb-stream nb-{}\">{}</pre>",
+            output["name"].as_str().unwrap_or("stdout"),
+            escape_html(&text(&output["text"]))
+        ),
+        Some("execute_result") | Some("display_data") => {
+            let data = &output["data"];
+
+            if !data["text/html"].is_null() {
+                text(&data["text/html"])
+            } else if let Some(png) = data["image/png"].as_str() {
+                format!(
+                    "<img src=\"data:image/png;base64,{}\" alt=\"\">",
+                    png.split_whitespace().collect::<String>()
+                )
+            } else if let Some(jpeg) = data["image/jpeg"].as_str() {
+                format!(
+                    "<img src=\"data:image/jpeg;base64,{}\" alt=\"\">",
+                    jpeg.split_whitespace().collect::<String>()
+                )
+            } else if !data["image/svg+xml"].is_null() {
+                text(&data["image/svg+xml"])
+            } else if !data["text/plain"].is_null() {
+                format!(
+                    "<pre class=\"nb-result\">{}</pre>",
+                    escape_html(&text(&data["text/plain"]))
+                )
+            } else {
+                String::new()
+            }
+        }
+        Some("error") => format!(
+            "<pre class=\"nb-error\">{}: {}</pre>",
+            escape_html(output["ename"].as_str().unwrap_or("Error")),
+            escape_html(output["evalue"].as_str().unwrap_or(""))
+        ),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tera::Tera;
+
+    const NOTEBOOK: &str = r##"{
+      "metadata": {"language_info": {"name": "python"}},
+      "cells": [
+        {"cell_type": "markdown", "source": ["# Results\n", "Some *text*."]},
+        {
+          "cell_type": "code",
+          "source": "print('a < b')",
+          "outputs": [
+            {"output_type": "stream", "name": "stdout", "text": ["a < b\n"]},
+            {"output_type": "execute_result", "data": {"text/plain": "42"}}
+          ]
+        },
+        {"cell_type": "code", "source": "total = 1", "outputs": []},
+        {"cell_type": "raw", "source": "ignored"}
+      ]
+    }"##;
+
+    fn render_notebook(content: &str) -> Result<Rendered, BuildError> {
+        let templates = Tera::default();
+        let fm = Yaml::Hash(YamlHash::new());
+        let opts = RenderOptions::new(Path::new("notebook.ipynb"), 0, &templates, &fm)?;
+
+        render(content, &fm, &opts)
+    }
+
+    #[test]
+    fn renders_cells_in_order_with_their_outputs() {
+        let html = render_notebook(NOTEBOOK).unwrap().html;
+        let positions = [
+            "<h1 id=\"results\">Results",
+            "<em>text</em>",
+            "print",
+            "<div class=\"nb-output\" data-output=\"0\">",
+            "<pre class=\"nb-stream nb-stdout\">a &lt; b",
+            "<pre class=\"nb-result\">42</pre></div>",
+            "total",
+        ]
+        .iter()
+        .map(|needle| {
+            html.find(needle)
+                .unwrap_or_else(|| panic!("{} in {}", needle, html))
+        })
+        .collect::<Vec<usize>>();
+
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(html.matches("nb-output").count(), 1);
+        assert!(!html.contains("ignored"));
+    }
+
+    #[test]
+    fn invalid_notebooks_are_located() {
+        let error = render_notebook("{\n  \"cells\": [\n}").err().unwrap();
+
+        assert_eq!(error.line, Some(3));
+    }
+}
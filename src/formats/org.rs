@@ -0,0 +1,158 @@
+use super::{map_text, replace_spans};
+
+/// Translates Org to Markdown: `*` headlines, `#+BEGIN_SRC`/`EXAMPLE`/`QUOTE` blocks,
+/// `#+BEGIN_NOTE`-style admonitions, `*bold*`, `/italic/`, `=verbatim=`, `~code~`, `+strike+`,
+/// `_underline_`, `[[target][description]]` links and tables. Keyword lines such as `#+TITLE:`,
+/// comments and property drawers are dropped.
+pub fn to_markdown(content: &str) -> String {
+    let mut markdown = String::with_capacity(content.len());
+    let mut in_src = false;
+    let mut in_quote = false;
+    let mut in_drawer = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        let keyword = trimmed.to_lowercase();
+
+        if in_src {
+            match keyword.starts_with("#+end_src") || keyword.starts_with("#+end_example") {
+                true => {
+                    in_src = false;
+                    markdown += "```\n";
+                }
+                false => {
+                    markdown += line.strip_prefix(',').unwrap_or(line);
+                    markdown += "\n";
+                }
+            }
+
+            continue;
+        }
+
+        if in_drawer {
+            in_drawer = !keyword.starts_with(":end:");
+            markdown += "\n";
+            continue;
+        }
+
+        if let Some(args) = keyword.strip_prefix("#+begin_src") {
+            let lang = args.split_whitespace().next().unwrap_or("");
+            in_src = true;
+            markdown += &format!("```{}\n", lang);
+        } else if keyword.starts_with("#+begin_example") {
+            in_src = true;
+            markdown += "```\n";
+        } else if keyword.starts_with("#+begin_quote") {
+            in_quote = true;
+            markdown += "\n";
+        } else if keyword.starts_with("#+end_quote") {
+            in_quote = false;
+            markdown += "\n";
+        } else if let Some(kind) = keyword.strip_prefix("#+begin_") {
+            markdown += &format!(":::{}\n", kind.trim());
+        } else if keyword.starts_with("#+end_") {
+            markdown += ":::\n";
+        } else if keyword.starts_with("#+") || trimmed == "#" || trimmed.starts_with("# ") {
+            markdown += "\n";
+        } else if keyword == ":properties:" {
+            in_drawer = true;
+            markdown += "\n";
+        } else if in_quote {
+            markdown += &format!("> {}\n", inline(trimmed));
+        } else if let Some(heading) = headline(line) {
+            markdown += &heading;
+            markdown += "\n";
+        } else if trimmed.starts_with('|') {
+            markdown += &table_row(trimmed);
+            markdown += "\n";
+        } else {
+            markdown += &inline(line);
+            markdown += "\n";
+        }
+    }
+
+    markdown
+}
+
+/// Rewrites `** Title` as `## Title`, dropping `TODO`/`DONE` keywords and `:tags:`.
+fn headline(line: &str) -> Option<String> {
+    let level = line.len() - line.trim_start_matches('*').len();
+    let title = line[level..].strip_prefix(' ')?;
+
+    if level == 0 {
+        return None;
+    }
+
+    let title = title
+        .trim()
+        .trim_start_matches("TODO ")
+        .trim_start_matches("DONE ");
+
+    let title = match title.rfind(" :") {
+        Some(i) if title.ends_with(':') && !title[i + 2..].contains(' ') => &title[..i],
+        _ => title,
+    };
+
+    Some(format!(
+        "{} {}",
+        "#".repeat(level.min(6)),
+        inline(title.trim())
+    ))
+}
+
+/// Rewrites `|---+---|` separators as `|---|---|`.
+fn table_row(line: &str) -> String {
+    match line.starts_with("|-") {
+        true => line.replace('+', "|"),
+        false => inline(line),
+    }
+}
+
+fn inline(line: &str) -> String {
+    let line = replace_spans(line, "=", "=", |s| format!("`{}`", s));
+    let line = replace_spans(&line, "~", "~", |s| format!("`{}`", s));
+
+    map_text(&line, |text| {
+        let text = replace_spans(text, "*", "*", |s| format!("**{}**", s));
+        let text = replace_spans(&text, "/", "/", |s| format!("*{}*", s));
+        let text = replace_spans(&text, "+", "+", |s| format!("~~{}~~", s));
+        let text = replace_spans(&text, "_", "_", |s| format!("<u>{}</u>", s));
+        links(&text)
+    })
+}
+
+/// Rewrites `[[target][description]]` as `[description](target)` and `[[https://...]]` as an
+/// autolink. `file:` prefixes are dropped; other `[[Title]]` links are left to the wiki link
+/// resolution.
+fn links(text: &str) -> String {
+    let mut rewritten = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("[[") {
+        let end = match rest[start..].find("]]") {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let inner = &rest[start + 2..end];
+        let (target, description) = match inner.find("][") {
+            Some(i) => (&inner[..i], Some(&inner[i + 2..])),
+            None => (inner, None),
+        };
+
+        let target = target.strip_prefix("file:").unwrap_or(target);
+        let is_url = target.contains("://");
+
+        rewritten += &rest[..start];
+        rewritten += &match description {
+            Some(description) => format!("[{}]({})", description, target),
+            None if is_url => format!("<{}>", target),
+            None => format!("[[{}]]", target),
+        };
+
+        rest = &rest[end + 2..];
+    }
+
+    rewritten += rest;
+    rewritten
+}
@@ -0,0 +1,236 @@
+use crate::errors::{self, BuildError};
+use serde_json::Value as JsonValue;
+use toml::Value as TomlValue;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::{Yaml, YamlLoader};
+
+const BOM: &str = "\u{feff}";
+
+/// Splits the frontmatter from a file: YAML between `---` lines, TOML between `+++` lines or, when
+/// `allow_json` is set, a leading JSON object. A byte order mark and CRLF line endings are
+/// accepted. Returns the frontmatter, if the file starts with one, and the offset of the content.
+/// Parse errors are located relative to the start of `contents`.
+pub fn split(contents: &str, allow_json: bool) -> Result<(Option<Yaml>, usize), BuildError> {
+    let start = if contents.starts_with(BOM) {
+        BOM.len()
+    } else {
+        0
+    };
+    let rest = &contents[start..];
+    let first_line = rest.lines().next().unwrap_or("").trim_end();
+
+    match first_line {
+        "---" | "+++" => {
+            let fm_start = start + line_len(rest);
+            let (fm_end, content_start) = match find_closing(contents, fm_start, first_line) {
+                Some(found) => found,
+                None => {
+                    return Err(BuildError::new(format!(
+                        "Failed to find the closing '{}' of the frontmatter.",
+                        first_line
+                    ))
+                    .at_offset(contents, start, 0))
+                }
+            };
+
+            let frontmatter = &contents[fm_start..fm_end];
+            let fm_line_offset = contents[..fm_start].matches('\n').count();
+            let yaml = match first_line {
+                "---" => parse_yaml(frontmatter),
+                _ => parse_toml(frontmatter),
+            }
+            .map_err(|e| e.shifted(fm_line_offset))?;
+
+            Ok((Some(yaml), content_start))
+        }
+        _ if allow_json && rest.starts_with('{') => {
+            let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<JsonValue>();
+            let value = match stream.next() {
+                Some(Ok(value)) => value,
+                Some(Err(e)) => {
+                    return Err(invalid("JSON", &e).at(e.line(), Some(e.column())));
+                }
+                None => return Ok((None, start)),
+            };
+
+            let fm_end = start + stream.byte_offset();
+            let rest_of_line = line_len(&contents[fm_end..]);
+            let content_start = match contents[fm_end..fm_end + rest_of_line].trim().is_empty() {
+                true => fm_end + rest_of_line,
+                false => fm_end,
+            };
+
+            Ok((Some(json_to_yaml(&value)), content_start))
+        }
+        _ => Ok((None, start)),
+    }
+}
+
+/// Finds the line holding only `delimiter` after `from`, returning where it starts and where the
+/// content following it starts.
+fn find_closing(contents: &str, from: usize, delimiter: &str) -> Option<(usize, usize)> {
+    let mut pos = from;
+
+    while pos < contents.len() {
+        let len = line_len(&contents[pos..]);
+
+        if contents[pos..pos + len].trim_end() == delimiter {
+            return Some((pos, pos + len));
+        }
+
+        pos += len;
+    }
+
+    None
+}
+
+/// Length of the first line of `s`, including its line ending.
+fn line_len(s: &str) -> usize {
+    s.find('\n').map_or(s.len(), |i| i + 1)
+}
+
+pub fn parse_yaml(frontmatter: &str) -> Result<Yaml, BuildError> {
+    let mut docs = YamlLoader::load_from_str(frontmatter).map_err(|e| {
+        let marker = e.marker();
+        invalid("YAML", &e).at(marker.line(), Some(marker.col() + 1))
+    })?;
+
+    match docs.pop() {
+        Some(yaml) => Ok(yaml),
+        None => Ok(Yaml::Hash(YamlHash::new())),
+    }
+}
+
+fn parse_toml(frontmatter: &str) -> Result<Yaml, BuildError> {
+    let value = frontmatter
+        .parse::<TomlValue>()
+        .map_err(|e| match e.line_col() {
+            Some((line, col)) => invalid("TOML", &e).at(line + 1, Some(col + 1)),
+            None => invalid("TOML", &e),
+        })?;
+
+    Ok(toml_to_yaml(&value))
+}
+
+fn invalid<E: std::fmt::Display>(language: &str, e: &E) -> BuildError {
+    BuildError::new(format!("Failed to parse frontmatter as {}.", language))
+        .with_cause(errors::without_position(&e.to_string()))
+}
+
+pub fn json_to_yaml(value: &JsonValue) -> Yaml {
+    match value {
+        JsonValue::Null => Yaml::Null,
+        JsonValue::Bool(b) => Yaml::Boolean(*b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        JsonValue::String(s) => Yaml::String(s.clone()),
+        JsonValue::Array(values) => Yaml::Array(values.iter().map(json_to_yaml).collect()),
+        JsonValue::Object(map) => Yaml::Hash(
+            map.iter()
+                .map(|(k, v)| (Yaml::String(k.clone()), json_to_yaml(v)))
+                .collect::<YamlHash>(),
+        ),
+    }
+}
+
+/// Converts TOML to YAML; dates become strings, as they are in YAML frontmatter.
+fn toml_to_yaml(value: &TomlValue) -> Yaml {
+    match value {
+        TomlValue::String(s) => Yaml::String(s.clone()),
+        TomlValue::Integer(i) => Yaml::Integer(*i),
+        TomlValue::Float(f) => Yaml::Real(f.to_string()),
+        TomlValue::Boolean(b) => Yaml::Boolean(*b),
+        TomlValue::Datetime(d) => Yaml::String(d.to_string()),
+        TomlValue::Array(values) => Yaml::Array(values.iter().map(toml_to_yaml).collect()),
+        TomlValue::Table(table) => Yaml::Hash(
+            table
+                .iter()
+                .map(|(k, v)| (Yaml::String(k.clone()), toml_to_yaml(v)))
+                .collect::<YamlHash>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn title(fm: &Option<Yaml>) -> Option<&str> {
+        fm.as_ref().and_then(|fm| fm["title"].as_str())
+    }
+
+    #[test]
+    fn skips_a_byte_order_mark() {
+        let contents = "\u{feff}---\ntitle: A\n---\nBody\n";
+        let (fm, offset) = split(contents, false).unwrap();
+
+        assert_eq!(title(&fm), Some("A"));
+        assert_eq!(&contents[offset..], "Body\n");
+
+        let (fm, offset) = split("\u{feff}Body", false).unwrap();
+
+        assert!(fm.is_none());
+        assert_eq!(offset, BOM.len());
+    }
+
+    #[test]
+    fn accepts_crlf_line_endings() {
+        let contents = "---\r\ntitle: A\r\n---\r\nBody\r\n";
+        let (fm, offset) = split(contents, false).unwrap();
+
+        assert_eq!(title(&fm), Some("A"));
+        assert_eq!(&contents[offset..], "Body\r\n");
+    }
+
+    #[test]
+    fn parses_toml_between_plus_lines() {
+        let contents = "+++\ntitle = \"A\"\ntags = [1, 2]\n+++\nBody";
+        let (fm, offset) = split(contents, false).unwrap();
+        let fm = fm.unwrap();
+
+        assert_eq!(fm["title"].as_str(), Some("A"));
+        assert_eq!(fm["tags"][1].as_i64(), Some(2));
+        assert_eq!(&contents[offset..], "Body");
+    }
+
+    #[test]
+    fn keeps_content_after_a_json_object_on_its_line() {
+        let contents = "{\"title\": \"A\"} Body\nMore";
+        let (fm, offset) = split(contents, true).unwrap();
+
+        assert_eq!(title(&fm), Some("A"));
+        assert_eq!(&contents[offset..], " Body\nMore");
+
+        let contents = "{\"title\": \"A\"}  \nBody";
+        let (_, offset) = split(contents, true).unwrap();
+
+        assert_eq!(&contents[offset..], "Body");
+
+        let (fm, offset) = split(contents, false).unwrap();
+
+        assert!(fm.is_none());
+        assert_eq!(offset, 0);
+    }
+
+    #[test]
+    fn reports_a_missing_closing_delimiter() {
+        let error = split("\u{feff}+++\ntitle = \"A\"\nBody\n", false).unwrap_err();
+
+        assert!(error.message.contains("closing '+++'"));
+        assert_eq!((error.line, error.column), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn locates_errors_in_the_file() {
+        let error = split("---\ntitle: A\nkey: [1\n---\n", false).unwrap_err();
+        assert_eq!(error.line, Some(4));
+
+        let error = split("+++\ntitle = \"A\"\nkey = \n+++\n", false).unwrap_err();
+        assert_eq!(error.line, Some(3));
+
+        let error = split("\u{feff}{\n  \"title\": \"A\",\n  \"x\": }\nBody", true).unwrap_err();
+        assert_eq!(error.line, Some(3));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::errors::{BuildError, Diagnostics};
+use crate::formats;
+use crate::models::{Page, PageLink};
+use crate::slugs;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+/// `href` prefix of links to source files, followed by the file's absolute path.
+pub const FILE_LINK_PREFIX: &str = "thea-link:";
+/// `href` prefix of `[[wiki links]]`, followed by the target's title.
+pub const WIKI_LINK_PREFIX: &str = "thea-wiki:";
+
+/// Percent-encodes a wiki link target so it survives as an `href` attribute value.
+pub fn wiki_placeholder(target: &str) -> String {
+    let mut href = String::from(WIKI_LINK_PREFIX);
+
+    for byte in target.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'#' => {
+                href.push(byte as char)
+            }
+            byte => href += &format!("%{:02X}", byte),
+        }
+    }
+
+    href
+}
+
+/// Rewrites a relative link to a source file in one of the markup formats, e.g. `.md`, into a
+/// `thea-link:` placeholder resolved once every page's slug is known. Other links are returned
+/// unchanged.
+pub fn placeholder_for(dest: &str, source: &Path) -> Option<String> {
+    let is_relative = !dest.contains("://")
+        && !dest.starts_with('/')
+        && !dest.starts_with('#')
+        && !dest.starts_with("mailto:");
+
+    let (path, fragment) = match dest.find('#') {
+        Some(i) => (&dest[..i], &dest[i..]),
+        None => (dest, ""),
+    };
+
+    let is_markup = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(formats::is_markup);
+
+    if !is_relative || !is_markup {
+        return None;
+    }
+
+    let target = normalize(&source.parent()?.join(path));
+    Some(format!(
+        "{}{}{}",
+        FILE_LINK_PREFIX,
+        target.display(),
+        fragment
+    ))
+}
+
+/// Lexically resolves `.` and `..` components.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c.as_os_str()),
+        }
+    }
+
+    normalized
+}
+
+/// Replaces link placeholders in every page's content and summary with the target's slug and
+/// computes each page's backlinks. Links to missing files are reported as errors, `[[wiki links]]`
+/// to missing titles as warnings; both then point to `#`.
+pub fn resolve(hashmap: &mut HashMap<String, Page>, diagnostics: &mut Diagnostics) {
+    let by_source = hashmap
+        .values()
+        .map(|page| (normalize(&page.meta.source), page.slug.clone()))
+        .collect::<HashMap<PathBuf, String>>();
+
+    let by_title = hashmap
+        .values()
+        .map(|page| (page.title().to_lowercase(), page.slug.clone()))
+        .collect::<HashMap<String, String>>();
+
+    let slugs = hashmap.keys().cloned().collect::<HashSet<String>>();
+    let mut backlinks: HashMap<String, BTreeSet<String>> = HashMap::new();
+
+    for page in hashmap.values_mut() {
+        let source = page.meta.source.clone();
+        let mut targets = BTreeSet::new();
+        let mut missing = BTreeSet::new();
+        let mut missing_titles = BTreeSet::new();
+        let mut resolve_href = |href: &str| -> Option<String> {
+            if let Some(target) = href.strip_prefix(FILE_LINK_PREFIX) {
+                let (path, fragment) = split_fragment(target);
+                let path = PathBuf::from(percent_decode(path));
+
+                match by_source.get(&path) {
+                    Some(slug) => {
+                        targets.insert(slug.clone());
+                        Some(format!("{}{}", slug, fragment))
+                    }
+                    None => {
+                        missing.insert(path);
+                        Some(String::from("#"))
+                    }
+                }
+            } else if let Some(target) = href.strip_prefix(WIKI_LINK_PREFIX) {
+                let (title, heading) = split_fragment(target);
+                let title = percent_decode(title);
+
+                match by_title.get(&title.to_lowercase()) {
+                    Some(slug) if heading.is_empty() => {
+                        targets.insert(slug.clone());
+                        Some(slug.clone())
+                    }
+                    Some(slug) => {
+                        targets.insert(slug.clone());
+                        let id = slugs::slugify(&percent_decode(&heading[1..]));
+                        Some(format!("{}#{}", slug, id))
+                    }
+                    None => {
+                        missing_titles.insert(title);
+                        Some(String::from("#"))
+                    }
+                }
+            } else {
+                let (path, _) = split_fragment(href);
+
+                if slugs.contains(path) {
+                    targets.insert(path.to_string());
+                }
+
+                None
+            }
+        };
+
+        page.content = rewrite_hrefs(&page.content, &mut resolve_href);
+        page.summary = rewrite_hrefs(&page.summary, &mut resolve_href);
+
+        for path in missing {
+            diagnostics.report(missing_file_error(&source, &path));
+        }
+
+        for title in missing_titles {
+            let warning = BuildError::new(format!("No page titled '{}' for wiki link.", title));
+            diagnostics.warn(warning.at_mention(&source, &title));
+        }
+
+        for target in targets {
+            if target != page.slug {
+                backlinks
+                    .entry(target)
+                    .or_default()
+                    .insert(page.slug.clone());
+            }
+        }
+    }
+
+    for (target, sources) in backlinks {
+        let links = sources
+            .iter()
+            .map(|slug| PageLink {
+                slug: slug.clone(),
+                title: hashmap[slug].title(),
+            })
+            .collect::<Vec<PageLink>>();
+
+        if let Some(page) = hashmap.get_mut(&target) {
+            page.backlinks = links;
+        }
+    }
+}
+
+/// Points at the first mention of the missing file's name in the page's source.
+fn missing_file_error(source: &Path, path: &Path) -> BuildError {
+    let error = BuildError::new(format!("Link to missing file {}.", path.display()));
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+
+    error.at_mention(source, file_name)
+}
+
+/// Calls `resolve_href` with every `href` attribute value, replacing it with the returned value.
+fn rewrite_hrefs<F>(html: &str, resolve_href: &mut F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut rewritten = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find("href=\"") {
+        let value_start = start + 6;
+        let value_end = match rest[value_start..].find('"') {
+            Some(end) => value_start + end,
+            None => break,
+        };
+
+        rewritten += &rest[..value_start];
+
+        match resolve_href(&rest[value_start..value_end]) {
+            Some(href) => rewritten += &href,
+            None => rewritten += &rest[value_start..value_end],
+        }
+
+        rest = &rest[value_end..];
+    }
+
+    rewritten += rest;
+    rewritten
+}
+
+fn split_fragment(href: &str) -> (&str, &str) {
+    match href.find('#') {
+        Some(i) => (&href[..i], &href[i..]),
+        None => (href, ""),
+    }
+}
+
+/// Decodes the `%XX` escapes applied to link destinations, and the `&amp;` pulldown-cmark adds.
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).replace("&amp;", "&")
+}
@@ -1,5 +1,6 @@
 extern crate actix_rt;
 extern crate actix_web;
+extern crate chrono;
 #[macro_use]
 extern crate clap;
 extern crate config;
@@ -13,35 +14,54 @@ extern crate mime;
 extern crate notify;
 extern crate pulldown_cmark;
 extern crate serde;
+extern crate serde_json;
 extern crate syntect;
 extern crate tera;
+extern crate toml;
 extern crate walkdir;
 extern crate yaml_rust;
 
+mod admonitions;
+mod check;
 mod codeblocks;
 mod content;
+mod emoji;
+mod errors;
+mod formats;
+mod frontmatter;
+mod links;
 mod markdown;
+mod math;
 mod models;
+mod routes;
+mod scaffold;
+mod sections;
 mod settings;
+mod shortcodes;
+mod slugs;
+mod summary;
+mod typography;
 mod watcher;
 
 use crate::content::FileType;
 use crate::models::Page;
+use crate::settings::{Redirect, RedirectType, Settings};
 use actix_files::Files as ActixFiles;
 use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
 use actix_web::http::header::{ETag, EntityTag, IF_NONE_MATCH};
 use actix_web::http::StatusCode;
 use actix_web::Result as AppResult;
 use actix_web::{guard, middleware, web, App, HttpRequest, HttpResponse, HttpServer};
-use config::{Config, Value as ConfigValue};
 use std::collections::HashMap;
 use std::env;
 use std::io::Result as IoResult;
-use std::sync::RwLock;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, RwLock};
 
 lazy_static! {
-    pub static ref SETTINGS: Config = settings::new();
-    static ref CONTENT: RwLock<HashMap<String, Page>> = RwLock::new(content::build_hashmap());
+    pub static ref SETTINGS: RwLock<Arc<Settings>> = RwLock::new(Arc::new(settings::new()));
+    pub static ref CONTENT: RwLock<HashMap<String, Page>> = RwLock::new(content::build_hashmap());
     static ref SHOULD_CACHE: bool = should_cache();
 }
 
@@ -76,6 +96,20 @@ pub fn rebuild_site() {
     info!("Regenerated the HashMap.");
 }
 
+/// Lists the routes in development mode, as JSON with `?format=json` and filtered with
+/// `?filter=`.
+async fn list_routes(query: web::Query<HashMap<String, String>>) -> AppResult<HttpResponse> {
+    let content = CONTENT.read().unwrap();
+    let routes = routes::collect(&content, query.get("filter").map(String::as_str));
+
+    match query.get("format").map(String::as_str) {
+        Some("json") => Ok(HttpResponse::Ok().json(&routes)),
+        _ => Ok(HttpResponse::Ok()
+            .set(ContentType::plaintext())
+            .body(routes::to_text(&routes))),
+    }
+}
+
 async fn catchall(req: HttpRequest) -> AppResult<HttpResponse> {
     let content = CONTENT.read().unwrap();
     let slug = normalize_req_path(req.path());
@@ -121,27 +155,22 @@ fn normalize_req_path(path: &str) -> String {
 }
 
 async fn unmatched_slug(slug: &str) -> AppResult<HttpResponse> {
-    let redirects = SETTINGS.get_table("redirects").unwrap();
+    let settings = settings::current();
 
-    if let Some(redirect) = redirects.get(slug) {
-        return redirect_request(redirect.clone()).await;
+    if let Some(redirect) = settings.redirects.get(slug) {
+        return redirect_request(redirect).await;
     }
 
     not_found_response().await
 }
 
-async fn redirect_request(redirect: ConfigValue) -> AppResult<HttpResponse> {
-    let redirect_hashmap = redirect.into_table().unwrap();
-    let redirect_type = redirect_hashmap.get("type").unwrap().to_string();
-    let redirect_location = redirect_hashmap.get("to").unwrap().to_string();
-
-    let mut res = match redirect_type.as_ref() {
-        "permanent" => HttpResponse::PermanentRedirect(),
-        "temporary" => HttpResponse::TemporaryRedirect(),
-        _ => unreachable!(),
+async fn redirect_request(redirect: &Redirect) -> AppResult<HttpResponse> {
+    let mut res = match redirect.redirect_type {
+        RedirectType::Permanent => HttpResponse::PermanentRedirect(),
+        RedirectType::Temporary => HttpResponse::TemporaryRedirect(),
     };
 
-    res.set_header("Location", redirect_location);
+    res.set_header("Location", redirect.to.as_str());
     Ok(res.finish())
 }
 
@@ -181,16 +210,68 @@ async fn main() -> IoResult<()> {
         (author: "Elliot Jackson <elliot@elliotekj.com")
         (about: crate_description!())
         (@arg dev: -d --dev "Runs thea in web development mode")
-        (@arg PORT: -p --port +takes_value "Sets the port thea starts on"))
+        (@arg PORT: -p --port +takes_value "Sets the port thea starts on")
+        (@subcommand check =>
+            (about:
+                "Builds the site without serving it and reports broken links and other problems")
+            (@arg FORMAT: --format +takes_value possible_value[text json] default_value("text")
+                "Sets the report format"))
+        (@subcommand new =>
+            (about: "Creates a site with `new site <dir>` or a page with `new <page_type> <title>`")
+            (@arg KIND: +required "`site`, or the page type of the page to create")
+            (@arg NAME: +required "The site's directory, or the page's title"))
+        (@subcommand routes =>
+            (about: "Lists the served paths with their source, type and layout, and the redirects")
+            (@arg FILTER: "Only lists routes whose path, source, page type or target contain this")
+            (@arg FORMAT: --format +takes_value possible_value[text json] default_value("text")
+                "Sets the output format")))
     .get_matches();
 
     env::set_var("RUST_LOG", "info");
     env_logger::init();
 
+    // Creating a site mustn't load the config, which doesn't exist yet.
+    if let Some(matches) = matches.subcommand_matches("new") {
+        let name = matches.value_of("NAME").unwrap();
+        let created = match matches.value_of("KIND").unwrap() {
+            "site" => scaffold::new_site(Path::new(name)).map(|_| PathBuf::from(name)),
+            page_type => scaffold::new_content(page_type, name),
+        };
+
+        match created {
+            Ok(path) => info!("Created {}", path.display()),
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        return Ok(());
+    }
+
     let is_dev_mode = matches.is_present("dev");
     let should_cache = !is_dev_mode;
     env::set_var("THEA_SHOULD_CACHE", should_cache.to_string());
 
+    codeblocks::validate_themes();
+
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let passed = check::run(matches.value_of("FORMAT") == Some("json"));
+        process::exit(if passed { 0 } else { 1 });
+    }
+
+    if let Some(matches) = matches.subcommand_matches("routes") {
+        let content = CONTENT.read().unwrap();
+        let routes = routes::collect(&content, matches.value_of("FILTER"));
+
+        match matches.value_of("FORMAT") {
+            Some("json") => println!("{}", serde_json::to_string_pretty(&routes).unwrap()),
+            _ => print!("{}", routes::to_text(&routes)),
+        }
+
+        return Ok(());
+    }
+
     // Force the initialization of CONTENT so the first request after startup isn't delayed.
     lazy_static::initialize(&CONTENT);
 
@@ -199,10 +280,15 @@ async fn main() -> IoResult<()> {
     let port = matches.value_of("PORT").unwrap_or("8765");
     let url = format!("127.0.0.1:{}", port);
 
-    HttpServer::new(|| {
+    HttpServer::new(move || {
         App::new()
             .wrap(middleware::Logger::default())
             .wrap(middleware::Compress::default())
+            .configure(|cfg| {
+                if is_dev_mode {
+                    cfg.route(routes::ENDPOINT, web::get().to(list_routes));
+                }
+            })
             .service(ActixFiles::new("/static", "./static"))
             .default_service(
                 web::resource("").route(web::get().to(catchall)).route(
@@ -1,90 +1,664 @@
-use crate::codeblocks;
+use crate::admonitions::Expanded;
+use crate::errors::BuildError;
+use crate::models::TocEntry;
+use crate::summary::MORE_MARKER;
+use crate::typography::Typography;
+use crate::{admonitions, codeblocks, links, math, settings, shortcodes, slugs};
 use pulldown_cmark::{html as md_html, Options as MdOptions, Parser as MdParser};
 use pulldown_cmark::{Event, Tag};
-use syntect::easy::HighlightLines;
-use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use std::collections::{HashMap, VecDeque};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tera::Tera;
+use yaml_rust::Yaml;
 
-pub fn from(content: &str) -> String {
+#[derive(Clone)]
+pub struct RenderOptions<'a> {
+    pub path: &'a Path,
+    pub line_offset: usize,
+    pub templates: &'a Tera,
+    pub toc: bool,
+    pub toc_depth: u32,
+    pub heading_anchors: bool,
+    pub math: bool,
+    pub smart_punctuation: bool,
+    pub emoji: bool,
+    pub external_links: bool,
+    pub lazy_images: bool,
+}
+
+pub struct Rendered {
+    pub html: String,
+    pub summary: Option<String>,
+    pub toc: Vec<TocEntry>,
+    pub dependencies: Vec<PathBuf>,
+}
+
+enum MathExpansion<'a> {
+    None,
+    /// Text preceding the math, if any, and the math.
+    Math(Option<Event<'a>>, math::Math<'a>),
+    /// Dollars that didn't open math after all.
+    Text(Event<'a>),
+}
+
+/// A run of lines that's either a fenced code block, fences included, or the text between them.
+pub struct Region {
+    pub range: Range<usize>,
+    pub is_code: bool,
+}
+
+struct OpenHeading<'a> {
+    level: u32,
+    /// The heading's text and code, used for its id and table of contents entry.
+    title: String,
+    events: Vec<Event<'a>>,
+}
+
+#[derive(Default)]
+struct Footnotes {
+    numbers: HashMap<String, usize>,
+    references: HashMap<String, usize>,
+    /// Labels and rendered definitions, in source order.
+    definitions: Vec<(String, String)>,
+    open: Option<(String, usize)>,
+}
+
+struct OpenCodeBlock {
+    open: codeblocks::CodeBlockOpen,
+    code: String,
+    offset: usize,
+}
+
+impl<'a> RenderOptions<'a> {
+    /// Reads the site-wide defaults, overridden by the page's `toc`, `toc_depth`,
+    /// `heading_anchors`, `math`, `smart_punctuation`, `emoji`, `external_links` and
+    /// `lazy_images` frontmatter keys. `line_offset` is the number of lines preceding the
+    /// content in the file at `path`, used when reporting errors.
+    pub fn new(
+        path: &'a Path,
+        line_offset: usize,
+        templates: &'a Tera,
+        fm: &Yaml,
+    ) -> Result<RenderOptions<'a>, BuildError> {
+        let settings = settings::current();
+        let site = &settings.content;
+
+        Ok(RenderOptions {
+            path,
+            line_offset,
+            templates,
+            toc: fm["toc"].as_bool().unwrap_or(true),
+            toc_depth: match fm["toc_depth"].as_i64() {
+                Some(depth) if depth < 0 => {
+                    return Err(BuildError::new("Invalid value for `toc_depth`.")
+                        .with_cause(format!("expected a number of at least 0, found {}", depth))
+                        .at_mention(path, "toc_depth"))
+                }
+                Some(depth) => depth.min(u32::MAX as i64) as u32,
+                None => site.toc_depth,
+            },
+            heading_anchors: page_or_site_flag(fm, "heading_anchors", site.heading_anchors),
+            math: page_or_site_flag(fm, "math", site.math),
+            smart_punctuation: page_or_site_flag(fm, "smart_punctuation", site.smart_punctuation),
+            emoji: page_or_site_flag(fm, "emoji", site.emoji),
+            external_links: page_or_site_flag(fm, "external_links", site.external_links),
+            lazy_images: page_or_site_flag(fm, "lazy_images", site.lazy_images),
+        })
+    }
+}
+
+/// The page's boolean frontmatter `key`, falling back to the `content.<key>` setting.
+fn page_or_site_flag(fm: &Yaml, key: &str, site_flag: bool) -> bool {
+    fm[key].as_bool().unwrap_or(site_flag)
+}
+
+pub fn from(content: &str, opts: &RenderOptions) -> Result<Rendered, BuildError> {
+    let content = shortcodes::expand(content, opts.path, opts.line_offset, opts.templates)?;
+    let expanded = admonitions::expand(&content);
+    let content = &expanded.text;
     let options = MdOptions::all();
-    let mut codeblock_open: Option<codeblocks::CodeBlockOpen> = None;
-    let mut highlighter: Option<HighlightLines> = None;
+    let mut codeblock: Option<OpenCodeBlock> = None;
+    let mut codeblock_count = 0;
+    let mut dependencies = Vec::new();
+    let mut footnotes = Footnotes::default();
+    let mut heading: Option<OpenHeading> = None;
+    let mut heading_ids: HashMap<String, usize> = HashMap::new();
+    let mut toc: Vec<TocEntry> = Vec::new();
+    let mut events = Vec::new();
+    let mut summary: Option<String> = None;
+    let mut typography = Typography::new(opts);
     let mut html_output = String::new();
 
-    let events = MdParser::new_ext(content, options)
-        .map(|event| match event {
+    let mut parser = MdParser::new_ext(content, options)
+        .into_offset_iter()
+        .peekable();
+
+    // Events pulled ahead of the parser, such as the text following math, to process first.
+    let mut pending = VecDeque::new();
+
+    while let Some((event, range)) = pending.pop_front().or_else(|| parser.next()) {
+        let event = match event {
+            Event::Text(text) if opts.math && codeblock.is_none() => {
+                match expand_math(content, &text, &range, &mut parser, &mut pending) {
+                    MathExpansion::Math(before, math) => {
+                        events.extend(before);
+
+                        match math.to_mathml() {
+                            Ok(mathml) => Event::Html(mathml.into()),
+                            Err(e) => return Err(error_at(opts, &expanded, e.offset, &e.message)),
+                        }
+                    }
+                    MathExpansion::Text(event) => event,
+                    MathExpansion::None => Event::Text(text),
+                }
+            }
+            event => event,
+        };
+
+        let event = match event {
+            Event::Start(Tag::Link(link_type, dest, title)) => {
+                match links::placeholder_for(&dest, opts.path) {
+                    Some(placeholder) => {
+                        Event::Start(Tag::Link(link_type, placeholder.into(), title))
+                    }
+                    None => Event::Start(Tag::Link(link_type, dest, title)),
+                }
+            }
+            event => event,
+        };
+
+        let event = match typography.apply(event) {
+            Some(event) => event,
+            None => continue,
+        };
+
+        // Heading events go through the transforms below before being buffered, so the title is
+        // collected first, while inline code is still plain text.
+        if let Some(ref mut open_heading) = heading {
+            if let Event::Text(text) | Event::Code(text) = &event {
+                open_heading.title.push_str(text);
+            }
+        }
+
+        let event = match event {
+            Event::Start(Tag::Heading(level)) => {
+                heading = Some(OpenHeading {
+                    level,
+                    title: String::new(),
+                    events: Vec::new(),
+                });
+                continue;
+            }
+
             Event::Start(Tag::CodeBlock(info)) => {
-                codeblock_open = Some(codeblocks::parse_codeblock_open(info));
-                let cbo = codeblock_open.clone().unwrap();
-                highlighter = Some(codeblocks::get_highlighter(&cbo));
-                let mut html = String::from("");
+                codeblock = Some(OpenCodeBlock {
+                    open: codeblocks::parse_codeblock_open(info),
+                    code: String::new(),
+                    offset: range.start,
+                });
+                continue;
+            }
+
+            Event::Text(text) => match codeblock {
+                Some(ref mut open_codeblock) => {
+                    open_codeblock.code.push_str(&text);
+                    continue;
+                }
+                None => Event::Text(text),
+            },
+
+            Event::Code(code) => match pending
+                .front_mut()
+                .or_else(|| parser.peek_mut())
+                .and_then(take_inline_lang)
+            {
+                Some(lang) => match codeblocks::render_inline(&code, &lang) {
+                    Ok(html) => Event::Html(html.into()),
+                    Err(e) => return Err(locate(opts, &expanded, range.start, e)),
+                },
+                None => Event::Code(code),
+            },
 
-                if let Some(filename) = &cbo.filename {
-                    html += &format!("<span class=\"pre-filename\">{}</span>", filename);
-                };
+            Event::End(Tag::CodeBlock(_)) => {
+                let OpenCodeBlock {
+                    mut open,
+                    mut code,
+                    offset,
+                } = codeblock.take().unwrap();
+
+                if open.include.is_some() {
+                    let (included, dependency) = codeblocks::read_include(&mut open)
+                        .map_err(|e| error_at(opts, &expanded, offset, &e))?;
+
+                    code = included;
+                    dependencies.push(dependency);
+                }
 
-                match &cbo.lang {
-                    Some(lang) => html += &format!("<pre class=\"lang-{}\"><code>", lang),
-                    None => html += "<pre><code>",
-                };
+                codeblock_count += 1;
+                let html = codeblocks::render(&open, &code, codeblock_count)
+                    .map_err(|e| locate(opts, &expanded, offset, e))?;
 
                 Event::Html(html.into())
             }
 
-            Event::Text(text) => {
-                if let Some(ref mut highlighter) = highlighter {
-                    let cbo = codeblock_open.clone().unwrap();
-                    let hltd = highlighter.highlight(&text, &codeblocks::SYNTAX_SET);
-
-                    let mut html = styled_line_to_highlighted_html(&hltd, IncludeBackground::No)
-                        .split("\n")
-                        .enumerate()
-                        .map(|(i, line)| {
-                            let line_nb = i + 1;
-                            let mut final_line = String::from("<div class=\"line");
-
-                            if let Some(highlighted_lines) = &cbo.highlights {
-                                if highlighted_lines.contains(&line_nb) {
-                                    final_line += " line-highlight\">";
-                                } else {
-                                    final_line += "\">";
-                                }
-                            } else {
-                                final_line += "\">";
-                            }
-
-                            final_line += &format!("<span class=\"line-nb\">{}</span>", line_nb);
-
-                            final_line += line;
-
-                            if let Some(highlighted_lines) = &cbo.highlights {
-                                if highlighted_lines.contains(&line_nb) {
-                                    final_line += "</span>";
-                                }
-                            }
-
-                            final_line += "</div>";
-                            final_line
-                        })
-                        .collect::<Vec<String>>();
-
-                    html.pop();
-                    return Event::Html(html.join("").into());
+            Event::FootnoteReference(label) => Event::Html(footnotes.reference(&label).into()),
+
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                footnotes.open = Some((label.to_string(), events.len()));
+                continue;
+            }
+
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                if let Some((label, start)) = footnotes.open.take() {
+                    let mut definition = String::new();
+                    push_html(&mut definition, events.drain(start..));
+
+                    // Like link reference definitions, the first definition of a label wins.
+                    if footnotes.definitions.iter().all(|(l, _)| *l != label) {
+                        footnotes.definitions.push((label, definition));
+                    }
                 }
 
-                Event::Text(text)
+                continue;
             }
 
-            Event::End(Tag::CodeBlock(_)) => {
-                codeblock_open = None;
-                highlighter = None;
-                Event::Html("</code></pre>".into())
+            Event::Html(html) => {
+                if summary.is_none() && html.trim() == MORE_MARKER {
+                    let mut summary_html = String::new();
+                    push_html(&mut summary_html, events.clone().into_iter());
+                    summary = Some(summary_html);
+                }
+
+                Event::Html(html)
             }
 
             _ => event,
-        })
-        .collect::<Vec<_>>();
+        };
+
+        if let Some(ref mut open_heading) = heading {
+            match event {
+                Event::End(Tag::Heading(_)) => {
+                    let level = open_heading.level;
+                    let title = std::mem::take(&mut open_heading.title);
+                    let id = unique_id(&title, &mut heading_ids);
+                    let mut html = format!("<h{} id=\"{}\">", level, id);
+
+                    push_html(&mut html, open_heading.events.drain(..));
+
+                    if opts.heading_anchors {
+                        html += &format!(
+                            "<a class=\"heading-anchor\" href=\"#{}\" aria-hidden=\"true\">#</a>",
+                            id
+                        );
+                    }
+
+                    html += &format!("</h{}>", level);
+
+                    if opts.toc && level <= opts.toc_depth {
+                        push_toc_entry(&mut toc, TocEntry::new(level, id, title));
+                    }
+
+                    heading = None;
+                    events.push(Event::Html(html.into()));
+                }
+                event => open_heading.events.push(event),
+            }
+
+            continue;
+        }
+
+        events.push(event);
+    }
+
+    push_html(&mut html_output, events.into_iter());
+    html_output += &footnotes.render();
+
+    Ok(Rendered {
+        html: html_output,
+        summary,
+        toc,
+        dependencies,
+    })
+}
+
+/// Finds math opening in the text event spanning `range` of `content`, reading it from the
+/// source so that backslashes and underscores aren't taken as Markdown. The events the math
+/// covers are dropped and the text after it is queued in `pending`. Math spanning code, HTML or
+/// several blocks is left as text.
+fn expand_math<'a, I>(
+    content: &'a str,
+    text: &str,
+    range: &Range<usize>,
+    parser: &mut Peekable<I>,
+    pending: &mut VecDeque<(Event<'a>, Range<usize>)>,
+) -> MathExpansion<'a>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    // Escapes and entities are text events that don't match their source.
+    if content.get(range.clone()) != Some(text) {
+        return MathExpansion::None;
+    }
+
+    let math = match math::find(content, range.start, range.end) {
+        Some(math) => math,
+        None => return MathExpansion::None,
+    };
+
+    let mut covered = vec![(Event::Text(text.to_string().into()), range.clone())];
+    let mut depth = 0;
+    let mut is_inline = true;
 
-    md_html::push_html(&mut html_output, events.into_iter());
+    while covered.last().unwrap().1.end < math.end {
+        let (event, event_range) = match pending.pop_front().or_else(|| parser.next()) {
+            Some(next) => next,
+            None => break,
+        };
+
+        match &event {
+            Event::Text(_) | Event::SoftBreak => {}
+            Event::Start(Tag::Emphasis) | Event::Start(Tag::Strong) => depth += 1,
+            Event::Start(Tag::Strikethrough) => depth += 1,
+            Event::End(Tag::Emphasis) | Event::End(Tag::Strong) => depth -= 1,
+            Event::End(Tag::Strikethrough) => depth -= 1,
+            _ => is_inline = false,
+        }
+
+        covered.push((event, event_range));
+
+        if !is_inline || depth < 0 {
+            break;
+        }
+    }
+
+    let last = covered.last().unwrap();
+    let ends_in_text = matches!(last.0, Event::Text(_)) && last.1.end >= math.end;
+
+    if !is_inline || depth != 0 || !ends_in_text {
+        // Keep the opening dollars as text and look for math after them.
+        let after_open = math.start + math.delimiter_len();
+
+        for event in covered.into_iter().skip(1).rev() {
+            pending.push_front(event);
+        }
+
+        if after_open < range.end {
+            let rest = &content[after_open..range.end];
+            pending.push_front((Event::Text(rest.into()), after_open..range.end));
+        }
+
+        return MathExpansion::Text(Event::Text(content[range.start..after_open].into()));
+    }
+
+    if last.1.end > math.end {
+        let rest = &content[math.end..last.1.end];
+        pending.push_front((Event::Text(rest.into()), math.end..last.1.end));
+    }
+
+    let before = match math.start > range.start {
+        true => Some(Event::Text(content[range.start..math.start].into())),
+        false => None,
+    };
+
+    MathExpansion::Math(before, math)
+}
+
+/// Splits `content` into fenced code blocks and the text between them, so that preprocessors
+/// such as shortcodes and admonitions can leave code as is. An unclosed fence runs to the end.
+pub fn fenced_regions(content: &str) -> Vec<Region> {
+    let mut regions = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut region_start = 0;
+    let mut pos = 0;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let line_start = pos;
+        pos += line.len();
+
+        match fence {
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                if line_start > region_start {
+                    regions.push(Region {
+                        range: region_start..line_start,
+                        is_code: false,
+                    });
+                }
+
+                region_start = line_start;
+                fence = Some(&trimmed[..3]);
+            }
+            Some(f) if trimmed.starts_with(f) => {
+                regions.push(Region {
+                    range: region_start..pos,
+                    is_code: true,
+                });
+                region_start = pos;
+                fence = None;
+            }
+            _ => {}
+        }
+    }
+
+    if region_start < content.len() {
+        regions.push(Region {
+            range: region_start..content.len(),
+            is_code: fence.is_some(),
+        });
+    }
+
+    regions
+}
+
+/// Renders `events` to HTML, turning `[[Title]]`, `[[Title|Label]]` and `[[Title#Heading]]` in
+/// text into wiki links resolved once every page is parsed. pulldown-cmark splits brackets into
+/// separate text events, so consecutive ones are merged first.
+fn push_html<'a, I>(html: &mut String, events: I)
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut merged: Vec<Event> = Vec::new();
+    let mut text = String::new();
+
+    for event in events {
+        match event {
+            Event::Text(t) => text.push_str(&t),
+            event => {
+                push_wiki_links(&mut merged, &mut text);
+                merged.push(event);
+            }
+        }
+    }
+
+    push_wiki_links(&mut merged, &mut text);
+    md_html::push_html(html, merged.into_iter());
+}
+
+fn push_wiki_links(events: &mut Vec<Event>, text: &mut String) {
+    let mut rest = text.as_str();
+
+    while let Some(start) = rest.find("[[") {
+        let end = match rest[start..].find("]]") {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let inner = &rest[start + 2..end];
+        let (target, label) = match inner.find('|') {
+            Some(i) => (inner[..i].trim(), inner[i + 1..].trim()),
+            None => (inner.trim(), inner.trim()),
+        };
+
+        if target.is_empty() || target.contains('[') {
+            events.push(Event::Text(rest[..start + 2].to_string().into()));
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        if start > 0 {
+            events.push(Event::Text(rest[..start].to_string().into()));
+        }
+
+        events.push(Event::Html(
+            format!(
+                "<a class=\"wiki-link\" href=\"{}\">{}</a>",
+                links::wiki_placeholder(target),
+                tera::escape_html(label)
+            )
+            .into(),
+        ));
+
+        rest = &rest[end + 2..];
+    }
+
+    if !rest.is_empty() {
+        events.push(Event::Text(rest.to_string().into()));
+    }
+
+    text.clear();
+}
+
+/// Builds an error pointing at the position of the file holding byte `offset` of the expanded
+/// content.
+fn error_at(opts: &RenderOptions, expanded: &Expanded, offset: usize, msg: &str) -> BuildError {
+    locate(opts, expanded, offset, BuildError::new(msg))
+}
+
+/// Points `error` at the position of the file holding byte `offset` of the expanded content,
+/// mapping its line back to the source.
+fn locate(
+    opts: &RenderOptions,
+    expanded: &Expanded,
+    offset: usize,
+    error: BuildError,
+) -> BuildError {
+    let mut error = error
+        .in_file(opts.path)
+        .at_offset(&expanded.text, offset, 0);
+    error.line = error
+        .line
+        .map(|line| opts.line_offset + expanded.source_line(line - 1) + 1);
+    error
+}
+
+impl Footnotes {
+    /// Numbers footnotes in the order they're first referenced.
+    fn reference(&mut self, label: &str) -> String {
+        let next_number = self.numbers.len() + 1;
+        let number = *self.numbers.entry(label.to_string()).or_insert(next_number);
+        let count = self.references.entry(label.to_string()).or_insert(0);
+        *count += 1;
+
+        format!(
+            "<sup class=\"footnote-ref\" id=\"{}\"><a href=\"#fn-{}\">{}</a></sup>",
+            reference_id(label, *count),
+            footnote_id(label),
+            number
+        )
+    }
+
+    /// Renders the definitions as an ordered list, each with links back to its references.
+    fn render(&mut self) -> String {
+        if self.definitions.is_empty() {
+            return String::new();
+        }
+
+        // Unreferenced definitions are numbered after the rest, in the order they're written.
+        for (label, _) in &self.definitions {
+            let next_number = self.numbers.len() + 1;
+            self.numbers.entry(label.clone()).or_insert(next_number);
+        }
+
+        let numbers = &self.numbers;
+        self.definitions.sort_by_key(|(label, _)| numbers[label]);
+
+        let mut html = String::from("<section class=\"footnotes\" role=\"doc-endnotes\"><ol>");
+
+        for (label, definition) in &self.definitions {
+            let count = self.references.get(label).cloned().unwrap_or(0);
+            let backrefs = (1..=count)
+                .map(|n| {
+                    format!(
+                        " <a href=\"#{}\" class=\"footnote-backref\" role=\"doc-backlink\">↩</a>",
+                        reference_id(label, n)
+                    )
+                })
+                .collect::<String>();
+
+            let definition = definition.trim_end();
+            let definition = match definition.strip_suffix("</p>") {
+                Some(body) => format!("{}{}</p>", body, backrefs),
+                None => format!("{}{}", definition, backrefs),
+            };
+
+            html += &format!(
+                "<li id=\"fn-{}\" value=\"{}\">{}</li>",
+                footnote_id(label),
+                self.numbers[label],
+                definition
+            );
+        }
+
+        html += "</ol></section>";
+        html
+    }
+}
+
+fn footnote_id(label: &str) -> String {
+    match slugs::slugify(label) {
+        id if id.is_empty() => label.to_string(),
+        id => id,
+    }
+}
+
+fn reference_id(label: &str, n: usize) -> String {
+    match n {
+        1 => format!("fnref-{}", footnote_id(label)),
+        n => format!("fnref-{}-{}", footnote_id(label), n),
+    }
+}
+
+/// Strips a `{:lang}` suffix from the text following an inline code span, returning `lang`.
+fn take_inline_lang(next: &mut (Event, std::ops::Range<usize>)) -> Option<String> {
+    let text = match &next.0 {
+        Event::Text(text) if text.starts_with("{:") => text,
+        _ => return None,
+    };
+
+    let end = text.find('}')?;
+    let lang = text[2..end].trim().to_string();
+
+    if lang.is_empty() || lang.contains(char::is_whitespace) {
+        return None;
+    }
+
+    next.0 = Event::Text(text[end + 1..].to_string().into());
+    Some(lang)
+}
+
+/// Slugifies a heading, suffixing `-1`, `-2`, ... when the id is already taken on the page.
+fn unique_id(title: &str, heading_ids: &mut HashMap<String, usize>) -> String {
+    let mut base = slugs::slugify(title);
+
+    if base.is_empty() {
+        base = "section".to_string();
+    }
+
+    let count = heading_ids.entry(base.clone()).or_insert(0);
+    let id = match *count {
+        0 => base.clone(),
+        n => format!("{}-{}", base, n),
+    };
+
+    *count += 1;
+    id
+}
 
-    html_output
+fn push_toc_entry(siblings: &mut Vec<TocEntry>, entry: TocEntry) {
+    match siblings.last_mut() {
+        Some(last) if last.level < entry.level => push_toc_entry(&mut last.children, entry),
+        _ => siblings.push(entry),
+    }
 }
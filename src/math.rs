@@ -0,0 +1,626 @@
+use tera::escape_html;
+
+struct Atom {
+    html: String,
+    /// Operators such as `\sum` and `\lim` whose scripts go above and below in display mode.
+    limits: bool,
+}
+
+struct TexParser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+    open_lefts: usize,
+}
+
+const GREEK: &[(&str, char)] = &[
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("gamma", 'γ'),
+    ("delta", 'δ'),
+    ("epsilon", 'ϵ'),
+    ("varepsilon", 'ε'),
+    ("zeta", 'ζ'),
+    ("eta", 'η'),
+    ("theta", 'θ'),
+    ("vartheta", 'ϑ'),
+    ("iota", 'ι'),
+    ("kappa", 'κ'),
+    ("lambda", 'λ'),
+    ("mu", 'μ'),
+    ("nu", 'ν'),
+    ("xi", 'ξ'),
+    ("pi", 'π'),
+    ("rho", 'ρ'),
+    ("sigma", 'σ'),
+    ("tau", 'τ'),
+    ("upsilon", 'υ'),
+    ("phi", 'ϕ'),
+    ("varphi", 'φ'),
+    ("chi", 'χ'),
+    ("psi", 'ψ'),
+    ("omega", 'ω'),
+    ("Gamma", 'Γ'),
+    ("Delta", 'Δ'),
+    ("Theta", 'Θ'),
+    ("Lambda", 'Λ'),
+    ("Xi", 'Ξ'),
+    ("Pi", 'Π'),
+    ("Sigma", 'Σ'),
+    ("Upsilon", 'Υ'),
+    ("Phi", 'Φ'),
+    ("Psi", 'Ψ'),
+    ("Omega", 'Ω'),
+    ("infty", '∞'),
+    ("partial", '∂'),
+    ("nabla", '∇'),
+    ("emptyset", '∅'),
+    ("hbar", 'ℏ'),
+    ("ell", 'ℓ'),
+];
+
+const OPERATORS: &[(&str, char)] = &[
+    ("cdot", '⋅'),
+    ("times", '×'),
+    ("div", '÷'),
+    ("pm", '±'),
+    ("mp", '∓'),
+    ("leq", '≤'),
+    ("le", '≤'),
+    ("geq", '≥'),
+    ("ge", '≥'),
+    ("neq", '≠'),
+    ("ne", '≠'),
+    ("approx", '≈'),
+    ("equiv", '≡'),
+    ("sim", '∼'),
+    ("propto", '∝'),
+    ("to", '→'),
+    ("rightarrow", '→'),
+    ("leftarrow", '←'),
+    ("Rightarrow", '⇒'),
+    ("Leftarrow", '⇐'),
+    ("leftrightarrow", '↔'),
+    ("iff", '⟺'),
+    ("implies", '⟹'),
+    ("mapsto", '↦'),
+    ("in", '∈'),
+    ("notin", '∉'),
+    ("subset", '⊂'),
+    ("subseteq", '⊆'),
+    ("supset", '⊃'),
+    ("supseteq", '⊇'),
+    ("cup", '∪'),
+    ("cap", '∩'),
+    ("setminus", '∖'),
+    ("forall", '∀'),
+    ("exists", '∃'),
+    ("neg", '¬'),
+    ("land", '∧'),
+    ("wedge", '∧'),
+    ("lor", '∨'),
+    ("vee", '∨'),
+    ("circ", '∘'),
+    ("ast", '∗'),
+    ("star", '⋆'),
+    ("ldots", '…'),
+    ("dots", '…'),
+    ("cdots", '⋯'),
+    ("langle", '⟨'),
+    ("rangle", '⟩'),
+    ("lfloor", '⌊'),
+    ("rfloor", '⌋'),
+    ("lceil", '⌈'),
+    ("rceil", '⌉'),
+    ("mid", '∣'),
+    ("parallel", '∥'),
+    ("perp", '⊥'),
+    ("angle", '∠'),
+    ("prime", '′'),
+];
+
+const LARGE_OPERATORS: &[(&str, char)] = &[
+    ("sum", '∑'),
+    ("prod", '∏'),
+    ("coprod", '∐'),
+    ("bigcup", '⋃'),
+    ("bigcap", '⋂'),
+];
+
+const INTEGRALS: &[(&str, char)] = &[("int", '∫'), ("iint", '∬'), ("oint", '∮')];
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "exp", "det", "dim", "gcd", "deg", "arg", "ker", "Pr",
+];
+
+const LIMIT_FUNCTIONS: &[&str] = &["lim", "max", "min", "sup", "inf", "liminf", "limsup"];
+
+const ACCENTS: &[(&str, char)] = &[
+    ("hat", '^'),
+    ("widehat", '^'),
+    ("bar", '¯'),
+    ("overline", '‾'),
+    ("vec", '→'),
+    ("dot", '˙'),
+    ("ddot", '¨'),
+    ("tilde", '~'),
+    ("widetilde", '~'),
+];
+
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.1667em"),
+    (":", "0.2222em"),
+    (">", "0.2222em"),
+    (";", "0.2778em"),
+    (" ", "0.25em"),
+    ("quad", "1em"),
+    ("qquad", "2em"),
+];
+
+const VARIANTS: &[(&str, &str)] = &[
+    ("mathrm", "normal"),
+    ("mathbf", "bold"),
+    ("mathit", "italic"),
+    ("mathbb", "double-struck"),
+    ("mathcal", "script"),
+    ("mathfrak", "fraktur"),
+    ("mathsf", "sans-serif"),
+    ("mathtt", "monospace"),
+];
+
+/// A LaTeX error and the byte offset in the Markdown source where it was found.
+pub struct MathError {
+    pub offset: usize,
+    pub message: String,
+}
+
+/// A `$inline$` or `$$display$$` expression in Markdown source.
+pub struct Math<'a> {
+    /// Byte offsets of the expression in the source, delimiters included.
+    pub start: usize,
+    pub end: usize,
+    pub tex: &'a str,
+    pub display: bool,
+}
+
+impl<'a> Math<'a> {
+    pub fn to_mathml(&self) -> Result<String, MathError> {
+        to_mathml(self.tex, self.display).map_err(|(pos, message)| {
+            let tex_offset = self
+                .tex
+                .char_indices()
+                .nth(pos)
+                .map_or(self.tex.len(), |(i, _)| i);
+
+            MathError {
+                offset: self.start + self.delimiter_len() + tex_offset,
+                message,
+            }
+        })
+    }
+
+    pub fn delimiter_len(&self) -> usize {
+        match self.display {
+            true => 2,
+            false => 1,
+        }
+    }
+}
+
+/// Finds the first expression in `source` that opens between bytes `from` and `to`. `\$` is left
+/// as is. The expression may extend past `to`; the caller checks it stays within inline content.
+pub fn find(source: &str, from: usize, to: usize) -> Option<Math<'_>> {
+    let mut pos = from;
+
+    while pos < to.min(source.len()) {
+        let rest = &source[pos..];
+        let backslashes = source[..pos].len() - source[..pos].trim_end_matches('\\').len();
+
+        if rest.starts_with('$') && backslashes % 2 == 1 {
+            pos += 1;
+        } else if let Some(inner) = rest.strip_prefix("$$") {
+            match inner.find("$$") {
+                Some(end) => {
+                    return Some(Math {
+                        start: pos,
+                        end: pos + end + 4,
+                        tex: &inner[..end],
+                        display: true,
+                    })
+                }
+                None => pos += 2,
+            }
+        } else if let Some(inner) = rest.strip_prefix('$') {
+            match find_inline_end(inner) {
+                Some(end) => {
+                    return Some(Math {
+                        start: pos,
+                        end: pos + end + 2,
+                        tex: &inner[..end],
+                        display: false,
+                    })
+                }
+                None => pos += 1,
+            }
+        } else {
+            pos += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+
+    None
+}
+
+/// Finds the `$` closing inline math, so that prices such as "$5 and $10" or paths such as
+/// `$HOME/bin:$PATH` aren't mistaken for math: the content can't start or end with whitespace,
+/// the closing `$` can't be followed by a letter or digit, and math doesn't contain code spans or
+/// span paragraphs.
+fn find_inline_end(inner: &str) -> Option<usize> {
+    if inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut escaped = false;
+
+    for (i, c) in inner.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '`' => return None,
+            '\n' if inner[i + 1..]
+                .trim_start_matches([' ', '\t'])
+                .starts_with('\n') =>
+            {
+                return None
+            }
+            '$' if i > 0 => {
+                let preceded_by_space = inner[..i].ends_with(char::is_whitespace);
+                let followed_by_word = inner[i + 1..].starts_with(char::is_alphanumeric);
+
+                if !preceded_by_space && !followed_by_word {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Converts a LaTeX math expression to a `<math>` element. Errors come with the index of the
+/// character they were found at.
+fn to_mathml(tex: &str, display: bool) -> Result<String, (usize, String)> {
+    let mut parser = TexParser {
+        chars: tex.chars().collect(),
+        pos: 0,
+        display,
+        open_lefts: 0,
+    };
+
+    let row = parser.parse_row(None).map_err(|e| (parser.pos, e))?;
+
+    Ok(match display {
+        true => format!("<math display=\"block\"><mrow>{}</mrow></math>", row),
+        false => format!("<math><mrow>{}</mrow></math>", row),
+    })
+}
+
+impl TexParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Parses atoms and their scripts until `end` (or the end of the expression), consuming `end`.
+    fn parse_row(&mut self, end: Option<char>) -> Result<String, String> {
+        let mut row = String::new();
+
+        loop {
+            self.skip_whitespace();
+
+            match self.peek() {
+                None if end.is_none() => return Ok(row),
+                None => return Err(format!("Missing '{}'.", end.unwrap())),
+                Some(c) if Some(c) == end => {
+                    self.pos += 1;
+                    return Ok(row);
+                }
+                Some('}') => return Err(String::from("Unbalanced '}'.")),
+                Some(_) => {}
+            }
+
+            if self.at_macro("right") {
+                return match end.is_none() && self.open_lefts > 0 {
+                    true => Ok(row),
+                    false => Err(String::from("'\\right' without a matching '\\left'.")),
+                };
+            }
+
+            let atom = self.parse_atom()?;
+            row += &self.parse_scripts(atom)?;
+        }
+    }
+
+    /// Attaches the `_` and `^` scripts following `atom`.
+    fn parse_scripts(&mut self, atom: Atom) -> Result<String, String> {
+        let mut sub = None;
+        let mut sup = None;
+
+        loop {
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.parse_argument()?);
+                }
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.parse_argument()?);
+                }
+                Some('_') | Some('^') => {
+                    return Err(String::from("Double subscript or superscript."))
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    let prime = sup.take().unwrap_or_default();
+                    sup = Some(format!("<mrow>{}<mo>′</mo></mrow>", prime));
+                }
+                _ => break,
+            }
+        }
+
+        let under_over = atom.limits && self.display;
+        let html = match (sub, sup) {
+            (None, None) => atom.html,
+            (Some(sub), None) if under_over => format!("<munder>{}{}</munder>", atom.html, sub),
+            (None, Some(sup)) if under_over => format!("<mover>{}{}</mover>", atom.html, sup),
+            (Some(sub), Some(sup)) if under_over => {
+                format!("<munderover>{}{}{}</munderover>", atom.html, sub, sup)
+            }
+            (Some(sub), None) => format!("<msub>{}{}</msub>", atom.html, sub),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", atom.html, sup),
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", atom.html, sub, sup),
+        };
+
+        Ok(html)
+    }
+
+    /// Parses a macro argument or script: a `{...}` group or a single atom.
+    fn parse_argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            None => Err(String::from("Missing argument.")),
+            Some('{') => {
+                self.pos += 1;
+                Ok(format!("<mrow>{}</mrow>", self.parse_row(Some('}'))?))
+            }
+            Some(_) => Ok(self.parse_atom()?.html),
+        }
+    }
+
+    /// Reads a `{...}` argument as raw text, for `\text` and font commands.
+    fn raw_argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+
+        if self.peek() != Some('{') {
+            return match self.peek() {
+                Some(c) => {
+                    self.pos += 1;
+                    Ok(c.to_string())
+                }
+                None => Err(String::from("Missing argument.")),
+            };
+        }
+
+        let start = self.pos + 1;
+        let mut depth = 0;
+
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 1 => return Ok(self.chars[start..self.pos - 1].iter().collect()),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+
+        Err(String::from("Missing '}'."))
+    }
+
+    fn parse_atom(&mut self) -> Result<Atom, String> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+
+        let html = match c {
+            '{' => format!("<mrow>{}</mrow>", self.parse_row(Some('}'))?),
+            '\\' => return self.parse_macro(),
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+
+                while let Some(next) = self.peek().filter(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(next);
+                    self.pos += 1;
+                }
+
+                format!("<mn>{}</mn>", number)
+            }
+            c if c.is_alphabetic() => format!("<mi>{}</mi>", c),
+            '-' => String::from("<mo>−</mo>"),
+            '\'' => String::from("<mo>′</mo>"),
+            '~' => String::from("<mspace width=\"0.25em\"></mspace>"),
+            '&' | '#' | '%' => {
+                self.pos -= 1;
+                return Err(format!("Unsupported character '{}'.", c));
+            }
+            c => format!("<mo>{}</mo>", escape_text(&c.to_string())),
+        };
+
+        Ok(Atom {
+            html,
+            limits: false,
+        })
+    }
+
+    fn at_macro(&self, name: &str) -> bool {
+        let end = self.pos + 1 + name.len();
+
+        self.peek() == Some('\\')
+            && self.chars.len() >= end
+            && self.chars[self.pos + 1..end].iter().collect::<String>() == name
+            && !self.chars.get(end).is_some_and(char::is_ascii_alphabetic)
+    }
+
+    fn parse_macro(&mut self) -> Result<Atom, String> {
+        let start = self.pos;
+
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            match self.peek() {
+                Some(_) => self.pos += 1,
+                None => return Err(String::from("Expected a macro name after '\\'.")),
+            }
+        }
+
+        let name = self.chars[start..self.pos].iter().collect::<String>();
+        let lookup = |table: &[(&str, char)]| table.iter().find(|(n, _)| *n == name).map(|e| e.1);
+        let mut limits = false;
+
+        let html = if let Some(c) = lookup(GREEK) {
+            format!("<mi>{}</mi>", c)
+        } else if let Some(c) = lookup(OPERATORS) {
+            format!("<mo>{}</mo>", c)
+        } else if let Some(c) = lookup(LARGE_OPERATORS) {
+            limits = true;
+            format!("<mo largeop=\"true\">{}</mo>", c)
+        } else if let Some(c) = lookup(INTEGRALS) {
+            format!("<mo largeop=\"true\">{}</mo>", c)
+        } else if FUNCTIONS.contains(&name.as_str()) {
+            format!("<mi>{}</mi>", name)
+        } else if LIMIT_FUNCTIONS.contains(&name.as_str()) {
+            limits = true;
+            format!("<mi>{}</mi>", name)
+        } else if let Some(c) = lookup(ACCENTS) {
+            format!(
+                "<mover accent=\"true\">{}<mo>{}</mo></mover>",
+                self.parse_argument()?,
+                escape_text(&c.to_string())
+            )
+        } else if let Some((_, width)) = SPACES.iter().find(|(n, _)| *n == name) {
+            format!("<mspace width=\"{}\"></mspace>", width)
+        } else if let Some((_, variant)) = VARIANTS.iter().find(|(n, _)| *n == name) {
+            self.raw_argument()?
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| match c.is_ascii_digit() {
+                    true => format!("<mn mathvariant=\"{}\">{}</mn>", variant, c),
+                    false => format!(
+                        "<mi mathvariant=\"{}\">{}</mi>",
+                        variant,
+                        escape_text(&c.to_string())
+                    ),
+                })
+                .collect()
+        } else {
+            match name.as_str() {
+                "{" | "}" | "|" | "%" | "$" | "#" | "&" | "_" => {
+                    let c = if name == "|" { "‖" } else { name.as_str() };
+                    format!("<mo>{}</mo>", escape_text(c))
+                }
+                "!" => String::new(),
+                "frac" | "dfrac" | "tfrac" => {
+                    let numerator = self.parse_argument()?;
+                    let denominator = self.parse_argument()?;
+                    format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+                }
+                "binom" => {
+                    let n = self.parse_argument()?;
+                    let k = self.parse_argument()?;
+                    format!(
+                        "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                        n, k
+                    )
+                }
+                "sqrt" => {
+                    self.skip_whitespace();
+
+                    if self.peek() == Some('[') {
+                        self.pos += 1;
+                        let index = self.parse_row(Some(']'))?;
+                        let base = self.parse_argument()?;
+                        format!("<mroot>{}<mrow>{}</mrow></mroot>", base, index)
+                    } else {
+                        format!("<msqrt>{}</msqrt>", self.parse_argument()?)
+                    }
+                }
+                "text" | "textrm" | "mbox" | "textit" | "textbf" => {
+                    format!("<mtext>{}</mtext>", escape_text(&self.raw_argument()?))
+                }
+                "operatorname" => format!("<mi>{}</mi>", escape_text(&self.raw_argument()?)),
+                "left" => {
+                    let open = self.parse_delimiter()?;
+                    self.open_lefts += 1;
+                    let body = self.parse_row(None)?;
+                    self.open_lefts -= 1;
+
+                    if !self.at_macro("right") {
+                        return Err(String::from("'\\left' without a matching '\\right'."));
+                    }
+
+                    self.pos += "\\right".len();
+                    let close = self.parse_delimiter()?;
+                    format!("<mrow>{}{}{}</mrow>", open, body, close)
+                }
+                _ => {
+                    self.pos = start - 1;
+                    return Err(format!("Unsupported LaTeX macro '\\{}'.", name));
+                }
+            }
+        };
+
+        Ok(Atom { html, limits })
+    }
+
+    /// Parses the delimiter following `\left` or `\right`; `.` is an invisible delimiter.
+    fn parse_delimiter(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+
+        let delimiter = match self.peek() {
+            Some('.') => {
+                self.pos += 1;
+                return Ok(String::new());
+            }
+            Some('\\') => {
+                self.pos += 1;
+                self.parse_macro()?.html
+            }
+            Some(_) => self.parse_atom()?.html,
+            None => return Err(String::from("Missing delimiter.")),
+        };
+
+        Ok(delimiter.replacen("<mo>", "<mo fence=\"true\" stretchy=\"true\">", 1))
+    }
+}
+
+/// Escapes text nodes so that the MathML survives as inline HTML in Markdown: ASCII punctuation
+/// is written as character references so it can't be read as emphasis or escapes.
+fn escape_text(text: &str) -> String {
+    text.chars()
+        .map(|c| match c.is_ascii_punctuation() {
+            true => format!("&#{};", c as u32),
+            false => escape_html(&c.to_string()),
+        })
+        .collect()
+}
@@ -1,4 +1,5 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use tera::{Map as TeraMap, Value as TeraValue};
 
 #[derive(Debug, Clone, Serialize)]
@@ -6,20 +7,68 @@ pub struct Page {
     pub page_type: String,
     pub slug: String,
     pub content: String,
+    pub summary: String,
+    pub word_count: usize,
+    pub reading_time: usize,
+    pub toc: Vec<TocEntry>,
     pub fm: TeraMap<String, TeraValue>,
+    pub is_section: bool,
+    pub section: Option<String>,
+    pub ancestors: Vec<String>,
+    pub backlinks: Vec<PageLink>,
     #[serde(skip_serializing)]
     pub meta: PageMeta,
 }
 
+impl Page {
+    /// The `title` frontmatter key, falling back to the last segment of the slug.
+    pub fn title(&self) -> String {
+        match self.fm.get("title").and_then(|t| t.as_str()) {
+            Some(title) => title.to_string(),
+            None => self.slug.rsplit('/').next().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PageLink {
+    pub slug: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TocEntry {
+    pub level: u32,
+    pub id: String,
+    pub title: String,
+    pub children: Vec<TocEntry>,
+}
+
+impl TocEntry {
+    pub fn new(level: u32, id: String, title: String) -> TocEntry {
+        TocEntry {
+            level,
+            id,
+            title,
+            children: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct PageMeta {
     pub etag: String,
     pub layout: Option<String>,
     pub rendered: Option<String>,
+    pub source: PathBuf,
+    pub dependencies: Vec<PathBuf>,
 }
 
+#[derive(Clone, Deserialize)]
 pub struct ConfigPageType {
+    #[serde(rename = "type")]
     pub ttype: String,
     pub path: String,
     pub default_layout: String,
+    pub permalink: Option<String>,
 }
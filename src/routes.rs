@@ -0,0 +1,162 @@
+use crate::content::{self, FileType};
+use crate::models::Page;
+use crate::settings;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+/// Path of the dev-mode endpoint listing the routes.
+pub const ENDPOINT: &str = "/_thea/routes";
+
+#[derive(Serialize)]
+pub struct Routes {
+    pub pages: Vec<PageRoute>,
+    pub redirects: Vec<RedirectRoute>,
+}
+
+#[derive(Serialize)]
+pub struct PageRoute {
+    pub path: String,
+    pub source: String,
+    pub page_type: String,
+    pub layout: Option<String>,
+    pub content_type: &'static str,
+    pub size: usize,
+    pub etag: String,
+}
+
+#[derive(Serialize)]
+pub struct RedirectRoute {
+    pub path: String,
+    pub to: String,
+    pub redirect_type: String,
+}
+
+/// Lists the pages of `content` and the configured redirects, sorted by path. With a `filter`,
+/// only routes whose path, source, page type or redirect target contain it are listed.
+pub fn collect(content: &HashMap<String, Page>, filter: Option<&str>) -> Routes {
+    let matches = |fields: &[&str]| match filter {
+        Some(filter) => fields.iter().any(|field| field.contains(filter)),
+        None => true,
+    };
+
+    let mut pages = content
+        .values()
+        .map(|page| PageRoute {
+            path: page.slug.clone(),
+            source: display_source(&page.meta.source),
+            page_type: page.page_type.clone(),
+            layout: page.meta.layout.clone(),
+            content_type: content_type(&page.slug),
+            size: page
+                .meta
+                .rendered
+                .as_ref()
+                .map_or(0, |rendered| rendered.len()),
+            etag: page.meta.etag.clone(),
+        })
+        .filter(|route| matches(&[&route.path, &route.source, &route.page_type]))
+        .collect::<Vec<PageRoute>>();
+
+    let mut redirects = settings::current()
+        .redirects
+        .values()
+        .map(|redirect| RedirectRoute {
+            path: redirect.from.clone(),
+            to: redirect.to.clone(),
+            redirect_type: redirect.redirect_type.to_string(),
+        })
+        .filter(|route| matches(&[&route.path, &route.to]))
+        .collect::<Vec<RedirectRoute>>();
+
+    pages.sort_by(|a, b| a.path.cmp(&b.path));
+    redirects.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Routes { pages, redirects }
+}
+
+/// Renders the routes as aligned columns, pages first.
+pub fn to_text(routes: &Routes) -> String {
+    let mut rows = vec![[
+        "PATH".to_string(),
+        "TYPE".to_string(),
+        "LAYOUT".to_string(),
+        "CONTENT TYPE".to_string(),
+        "SIZE".to_string(),
+        "ETAG".to_string(),
+        "SOURCE".to_string(),
+    ]];
+
+    for page in &routes.pages {
+        rows.push([
+            page.path.clone(),
+            page.page_type.clone(),
+            page.layout.clone().unwrap_or_else(|| String::from("-")),
+            page.content_type.to_string(),
+            page.size.to_string(),
+            page.etag.clone(),
+            page.source.clone(),
+        ]);
+    }
+
+    let mut widths = [0; 7];
+
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut text = String::new();
+
+    for row in &rows {
+        let cells = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<String>>();
+
+        text += cells.join("  ").trim_end();
+        text.push('\n');
+    }
+
+    if !routes.redirects.is_empty() {
+        text += "\nREDIRECTS\n";
+
+        for redirect in &routes.redirects {
+            text += &format!(
+                "{} -> {} ({})\n",
+                redirect.path, redirect.to, redirect.redirect_type
+            );
+        }
+    }
+
+    text
+}
+
+/// The `Content-Type` pages are served with.
+fn content_type(slug: &str) -> &'static str {
+    match content::get_filetype(slug) {
+        FileType::Html => "text/html; charset=utf-8",
+        FileType::Css => "text/css; charset=utf-8",
+        FileType::Js => "application/javascript; charset=utf-8",
+        FileType::Json => "application/json",
+        FileType::Xml => "text/xml",
+        FileType::Txt => "text/plain; charset=utf-8",
+    }
+}
+
+/// Shows sources relative to the site's directory. Generated pages have none.
+fn display_source(source: &Path) -> String {
+    if source.as_os_str().is_empty() {
+        return String::from("-");
+    }
+
+    let pwd = env::current_dir().unwrap_or_default();
+    source
+        .strip_prefix(&pwd)
+        .unwrap_or(source)
+        .display()
+        .to_string()
+}
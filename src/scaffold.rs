@@ -0,0 +1,239 @@
+use crate::errors::BuildError;
+use crate::{settings, slugs};
+use chrono::Local;
+use std::error::Error;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+use tera::{Context as TeraContext, Tera};
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::Yaml;
+
+/// Used by `new_content` when the site has no archetype for the page type, and written to
+/// `archetypes/default.md` by `new_site` as a starting point.
+const DEFAULT_ARCHETYPE: &str = r#"---
+title: {{ title | json_encode() | safe }}
+slug: {{ slug }}
+date: {{ date }}
+layout: {{ layout }}
+---
+
+"#;
+
+const CONFIG: &str = r#"content:
+  page_types:
+    - type: page
+      path: pages
+      default_layout: page.html
+templates:
+  globals:
+    site_title: {{ title | json_encode() | safe }}
+"#;
+
+const BASE_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{{ page.fm.title }} · {{ globals.site_title }}</title>
+  <link rel="stylesheet" href="/static/style.css">
+</head>
+<body>
+  <header><a href="/">{{ globals.site_title }}</a></header>
+  <main>
+    {% block content %}{% endblock content %}
+  </main>
+</body>
+</html>
+"#;
+
+const PAGE_TEMPLATE: &str = r#"{% extends "base.html" %}
+
+{% block content %}
+<h1>{{ page.fm.title }}</h1>
+{{ page.content | safe }}
+{% endblock content %}
+"#;
+
+const INDEX: &str = r#"---
+title: Home
+---
+
+Welcome to your new site. Add pages with `thea new page "Title"`.
+"#;
+
+const NOT_FOUND: &str = r#"---
+title: Page not found
+---
+
+There's nothing here. Head back to the [home page](index.md).
+"#;
+
+const STYLESHEET: &str = r#"body {
+  font-family: system-ui, sans-serif;
+  line-height: 1.6;
+  margin: 0 auto;
+  max-width: 40rem;
+  padding: 1rem;
+}
+"#;
+
+/// Creates a working site in `dir`: a config with a `page` page type, its layout, home and 404
+/// pages, a stylesheet in `static` and a default archetype.
+pub fn new_site(dir: &Path) -> Result<(), BuildError> {
+    let is_empty = match fs::read_dir(dir) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(_) => true,
+    };
+
+    if !is_empty {
+        return Err(BuildError::new(format!(
+            "{} already exists and isn't empty.",
+            dir.display()
+        )));
+    }
+
+    let title = dir
+        .canonicalize()
+        .unwrap_or_else(|_| dir.to_path_buf())
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("My site"));
+
+    let mut context = TeraContext::new();
+    context.insert("title", &title);
+
+    let files = [
+        ("config/default.yml", render(CONFIG, &context)?),
+        ("templates/base.html", BASE_TEMPLATE.to_string()),
+        ("templates/page.html", PAGE_TEMPLATE.to_string()),
+        ("content/pages/index.md", INDEX.to_string()),
+        ("content/pages/404.md", NOT_FOUND.to_string()),
+        ("static/style.css", STYLESHEET.to_string()),
+        ("archetypes/default.md", DEFAULT_ARCHETYPE.to_string()),
+    ];
+
+    for (rel_path, contents) in &files {
+        let path = dir.join(rel_path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
+
+/// Creates a page of `page_type` titled `title` in the page type's directory, from the
+/// `archetypes/<page_type>.*` template, falling back to `archetypes/default.*` and then to a
+/// built-in one. Archetypes get the `title`, `slug`, `date`, `layout` and `page_type` of the page
+/// and their extension sets the page's. Returns the path of the page.
+pub fn new_content(page_type: &str, title: &str) -> Result<PathBuf, BuildError> {
+    let settings = settings::current();
+    let page_types = &settings.content.page_types;
+
+    let pt = match page_types.iter().find(|pt| pt.ttype == page_type) {
+        Some(pt) => pt,
+        None => {
+            let known = page_types
+                .iter()
+                .map(|pt| pt.ttype.as_str())
+                .collect::<Vec<&str>>();
+
+            return Err(BuildError::new(format!(
+                "Unknown page type '{}'. Valid values: site/{}",
+                page_type,
+                known.join("/")
+            )));
+        }
+    };
+
+    let filename = slugs::slugify(title);
+
+    if filename.is_empty() {
+        return Err(BuildError::new(format!(
+            "Can't derive a file name from the title '{}'.",
+            title
+        )));
+    }
+
+    let (archetype, extension) = match find_archetype(page_type)? {
+        Some(path) => {
+            let extension = path.extension().unwrap().to_string_lossy().to_string();
+            (fs::read_to_string(&path)?, extension)
+        }
+        None => (DEFAULT_ARCHETYPE.to_string(), String::from("md")),
+    };
+
+    let rel_path = PathBuf::from(format!("{}.{}", filename, extension));
+    let date = Local::today().format("%Y-%m-%d").to_string();
+
+    let mut fm = YamlHash::new();
+    fm.insert(Yaml::from_str("title"), Yaml::String(title.to_string()));
+    fm.insert(Yaml::from_str("date"), Yaml::String(date.clone()));
+
+    let slug = match &pt.permalink {
+        Some(permalink) => slugs::expand_permalink(permalink, &rel_path, &Yaml::Hash(fm))?,
+        None => slugs::derive_slug(&rel_path),
+    };
+
+    let mut context = TeraContext::new();
+    context.insert("title", title);
+    context.insert("slug", &slug);
+    context.insert("date", &date);
+    context.insert("layout", &pt.default_layout);
+    context.insert("page_type", page_type);
+
+    let path = settings.content.path.join(&pt.path).join(&rel_path);
+
+    if path.exists() {
+        return Err(BuildError::new(format!(
+            "{} already exists.",
+            path.display()
+        )));
+    }
+
+    let contents = render(&archetype, &context)?;
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, contents)?;
+
+    Ok(path)
+}
+
+/// Finds the archetype for `page_type`, or the default one, in `content.archetypes_path`.
+fn find_archetype(page_type: &str) -> Result<Option<PathBuf>, IoError> {
+    let mut archetypes = match fs::read_dir(&settings::current().content.archetypes_path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().is_some())
+            .collect::<Vec<PathBuf>>(),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    archetypes.sort();
+
+    for stem in &[page_type, "default"] {
+        let found = archetypes
+            .iter()
+            .find(|path| path.file_stem().and_then(|s| s.to_str()) == Some(stem));
+
+        if let Some(path) = found {
+            return Ok(Some(path.clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+fn render(template: &str, context: &TeraContext) -> Result<String, BuildError> {
+    Tera::one_off(template, context, false).map_err(|e| {
+        let mut msg = format!("Failed to render the template: {}", e);
+        let mut cause = e.source();
+
+        while let Some(e) = cause {
+            msg += &format!(" - {}", e);
+            cause = e.source();
+        }
+
+        BuildError::new(msg)
+    })
+}
@@ -0,0 +1,196 @@
+use crate::formats;
+use crate::models::Page;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tera::Value as TeraValue;
+
+pub const INDEX_STEM: &str = "_index";
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub parent: Option<String>,
+    pub pages: Vec<String>,
+    pub subsections: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct SectionContext<'a> {
+    slug: &'a str,
+    title: String,
+    page: &'a Page,
+    pages: Vec<&'a Page>,
+    subsections: Vec<SectionContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct Breadcrumb {
+    slug: String,
+    title: String,
+}
+
+/// Whether `path` is an `_index` file in one of the markup formats, e.g. `_index.md`.
+pub fn is_section_index(path: &Path) -> bool {
+    let is_markup = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(formats::is_markup);
+
+    path.file_stem() == Some(OsStr::new(INDEX_STEM)) && is_markup
+}
+
+/// Links every page to its closest enclosing section (the nearest ancestor directory holding an
+/// `_index.md`) and returns the sections keyed by slug.
+pub fn assign(hashmap: &mut HashMap<String, Page>) -> HashMap<String, Section> {
+    let section_dirs = hashmap
+        .values()
+        .filter(|page| page.is_section)
+        .filter_map(|page| {
+            let dir = page.meta.source.parent()?;
+            Some((dir.to_path_buf(), page.slug.clone()))
+        })
+        .collect::<HashMap<PathBuf, String>>();
+
+    let mut sections = section_dirs
+        .values()
+        .map(|slug| {
+            let section = Section {
+                parent: None,
+                pages: Vec::new(),
+                subsections: Vec::new(),
+            };
+
+            (slug.clone(), section)
+        })
+        .collect::<HashMap<String, Section>>();
+
+    let parents = hashmap
+        .values()
+        .map(|page| (page.slug.clone(), find_parent(page, &section_dirs)))
+        .collect::<Vec<(String, Option<String>)>>();
+
+    for (slug, parent) in &parents {
+        let parent = match parent {
+            Some(parent) => parent,
+            None => continue,
+        };
+
+        if sections.contains_key(slug) {
+            sections.get_mut(slug).unwrap().parent = Some(parent.clone());
+            sections
+                .get_mut(parent)
+                .unwrap()
+                .subsections
+                .push(slug.clone());
+        } else {
+            sections.get_mut(parent).unwrap().pages.push(slug.clone());
+        }
+    }
+
+    for section in sections.values_mut() {
+        section
+            .pages
+            .sort_by(|a, b| order(&hashmap[a], &hashmap[b]));
+        section
+            .subsections
+            .sort_by(|a, b| order(&hashmap[a], &hashmap[b]));
+    }
+
+    for (slug, parent) in parents {
+        let page = hashmap.get_mut(&slug).unwrap();
+        page.ancestors = ancestors_of(parent.as_ref(), &sections);
+        page.section = parent;
+    }
+
+    sections
+}
+
+fn find_parent(page: &Page, section_dirs: &HashMap<PathBuf, String>) -> Option<String> {
+    let mut dir = page.meta.source.parent()?;
+
+    if page.is_section {
+        dir = dir.parent()?;
+    }
+
+    loop {
+        if let Some(slug) = section_dirs.get(dir) {
+            return Some(slug.clone());
+        }
+
+        dir = dir.parent()?;
+    }
+}
+
+fn ancestors_of(parent: Option<&String>, sections: &HashMap<String, Section>) -> Vec<String> {
+    let mut ancestors = Vec::new();
+    let mut current = parent;
+
+    while let Some(slug) = current {
+        ancestors.insert(0, slug.clone());
+        current = sections[slug].parent.as_ref();
+    }
+
+    ancestors
+}
+
+fn order(a: &Page, b: &Page) -> std::cmp::Ordering {
+    let weight = |p: &Page| p.fm.get("weight").and_then(|w| w.as_i64()).unwrap_or(0);
+
+    weight(a)
+        .cmp(&weight(b))
+        .then_with(|| a.title().cmp(&b.title()))
+}
+
+/// Serializes the section a page belongs to — the section itself for `_index.md` pages — for
+/// use as the `section` template variable.
+pub fn section_context(
+    page: &Page,
+    sections: &HashMap<String, Section>,
+    hashmap: &HashMap<String, Page>,
+) -> Option<TeraValue> {
+    let slug = match page.is_section {
+        true => &page.slug,
+        false => page.section.as_ref()?,
+    };
+
+    let context = build_section_context(slug, sections, hashmap);
+    tera::to_value(context).ok()
+}
+
+fn build_section_context<'a>(
+    slug: &'a str,
+    sections: &'a HashMap<String, Section>,
+    hashmap: &'a HashMap<String, Page>,
+) -> SectionContext<'a> {
+    let section = &sections[slug];
+    let page = &hashmap[slug];
+
+    SectionContext {
+        slug,
+        title: page.title(),
+        page,
+        pages: section.pages.iter().map(|s| &hashmap[s]).collect(),
+        subsections: section
+            .subsections
+            .iter()
+            .map(|s| build_section_context(s, sections, hashmap))
+            .collect(),
+    }
+}
+
+/// Builds the `breadcrumbs` template variable: every ancestor section followed by the page.
+pub fn breadcrumbs(page: &Page, hashmap: &HashMap<String, Page>) -> TeraValue {
+    let crumbs = page
+        .ancestors
+        .iter()
+        .filter_map(|slug| hashmap.get(slug))
+        .chain(std::iter::once(page))
+        .map(|p| Breadcrumb {
+            slug: p.slug.clone(),
+            title: p.title(),
+        })
+        .collect::<Vec<Breadcrumb>>();
+
+    tera::to_value(crumbs).unwrap_or(TeraValue::Null)
+}
@@ -1,91 +1,475 @@
-use config::{Config, File as ConfigFile, Value as ConfigValue};
+use crate::errors::{self, BuildError};
+use crate::models::ConfigPageType;
+use crate::{codeblocks, SETTINGS};
+use config::{Config, ConfigError, File as ConfigFile, Source};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use std::collections::HashMap;
-use std::{env, process};
+use std::path::{Path, PathBuf};
+#[cfg(not(test))]
+use std::process;
+use std::sync::Arc;
+use std::{env, fmt, fs};
+use tera::{Map as TeraMap, Value as TeraValue};
+use yaml_rust::ScanError;
 
-pub fn new() -> Config {
-    let mut config = default();
-    merge_files(&mut config);
-    parse_redirects(&mut config);
-    expand_paths(&mut config);
-    config
+pub const CONFIG_PATH: &str = "config";
+const CONFIG_NAMES: &[&str] = &["default", "development", "production"];
+const CONFIG_EXTENSIONS: &[&str] = &["toml", "json", "yaml", "yml", "hjson", "ini"];
+
+pub struct Settings {
+    pub content: ContentSettings,
+    pub templates: TemplatesSettings,
+    /// Keyed by the path they redirect from.
+    pub redirects: HashMap<String, Redirect>,
+    pub write_to_disk: bool,
 }
 
-fn default() -> Config {
-    let mut config = Config::default();
+pub struct ContentSettings {
+    pub path: PathBuf,
+    pub page_types: Vec<ConfigPageType>,
+    pub syntax_theme: String,
+    pub syntax_themes: Vec<String>,
+    pub syntax_highlighting: SyntaxHighlighting,
+    pub syntaxes_path: PathBuf,
+    pub themes_path: PathBuf,
+    pub archetypes_path: PathBuf,
+    pub admonition_class: String,
+    pub admonition_title_class: String,
+    pub heading_anchors: bool,
+    pub math: bool,
+    pub smart_punctuation: bool,
+    pub emoji: bool,
+    pub external_links: bool,
+    pub lazy_images: bool,
+    pub summary_words: usize,
+    pub words_per_minute: usize,
+    pub toc_depth: u32,
+}
 
-    config.set_default("content.path", "content").unwrap();
-    config
-        .set_default("content.syntax_theme", "InspiredGitHub")
-        .unwrap();
-    config.set_default("templates.path", "templates").unwrap();
-    config.set_default("write_to_disk", false).unwrap();
+pub struct TemplatesSettings {
+    pub path: PathBuf,
+    pub globals: TeraMap<String, TeraValue>,
+}
 
-    config
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyntaxHighlighting {
+    Inline,
+    Classes,
 }
 
-fn merge_files(config: &mut Config) {
-    let default_config = ConfigFile::with_name("./config/default").required(true);
-    config.merge(default_config).unwrap();
+#[derive(Clone, Deserialize)]
+pub struct Redirect {
+    pub from: String,
+    pub to: String,
+    #[serde(rename = "type")]
+    pub redirect_type: RedirectType,
+}
 
-    let mode = env::var("THEA_ENV").unwrap_or("development".into());
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectType {
+    Permanent,
+    Temporary,
+}
 
-    match mode.as_ref() {
-        "development" => {
-            let c = ConfigFile::with_name("./config/development").required(false);
-            config.merge(c).unwrap();
+impl fmt::Display for RedirectType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RedirectType::Permanent => write!(f, "permanent"),
+            RedirectType::Temporary => write!(f, "temporary"),
         }
-        "production" => {
-            let c = ConfigFile::with_name("./config/production").required(false);
-            config.merge(c).unwrap();
+    }
+}
+
+/// Reads settings out of the merged config files, collecting every problem rather than stopping
+/// at the first one.
+struct Reader<'a> {
+    config: &'a Config,
+    files: &'a [PathBuf],
+    errors: Vec<BuildError>,
+}
+
+#[cfg(not(test))]
+pub fn new() -> Settings {
+    match load() {
+        Ok(settings) => settings,
+        Err(errors) => {
+            report(&errors);
+            error!("Found {} problem(s) in the config.", errors.len());
+            process::exit(1);
         }
-        _ => warn!("default/development/production are the only valid config file names."),
+    }
+}
+
+/// Tests don't run inside a site, so they start from the defaults, with paths in the temporary
+/// directory rather than relative to the working directory.
+#[cfg(test)]
+pub fn new() -> Settings {
+    match read(&Config::default(), &[], &env::temp_dir().join("thea-tests")) {
+        Ok(settings) => settings,
+        Err(errors) => panic!("Invalid default settings: {:?}", errors),
+    }
+}
+
+/// The settings in effect. Hold on to them for the duration of a build so it isn't affected by a
+/// reload half-way through.
+pub fn current() -> Arc<Settings> {
+    SETTINGS.read().unwrap().clone()
+}
+
+/// Reloads the config files and swaps in the new settings if they're valid. Otherwise reports the
+/// problems and keeps the current settings. Returns whether the settings were swapped.
+pub fn reload() -> bool {
+    let settings = match load() {
+        Ok(settings) => settings,
+        Err(errors) => return keep_current(&errors),
     };
+
+    let errors = codeblocks::unknown_themes(&settings);
+
+    if !errors.is_empty() {
+        return keep_current(&errors);
+    }
+
+    let current = current();
+
+    // The syntax and theme sets are loaded once, on first use.
+    if settings.content.syntaxes_path != current.content.syntaxes_path
+        || settings.content.themes_path != current.content.themes_path
+    {
+        warn!(
+            "Changes to content.syntaxes_path and content.themes_path take effect after a restart."
+        );
+    }
+
+    *SETTINGS.write().unwrap() = Arc::new(settings);
+    info!("Reloaded the config.");
+    true
+}
+
+fn keep_current(errors: &[BuildError]) -> bool {
+    report(errors);
+    error!(
+        "Found {} problem(s) in the config; keeping the previous one.",
+        errors.len()
+    );
+    false
+}
+
+fn report(errors: &[BuildError]) {
+    for e in errors {
+        error!("{}", e.diagnostic());
+    }
+}
+
+/// Loads `config/default` and the `config/<THEA_ENV>` override, falling back to defaults for
+/// missing keys. Returns every invalid or unknown key found.
+pub fn load() -> Result<Settings, Vec<BuildError>> {
+    let mut config = Config::default();
+    let files = merge_files(&mut config).map_err(|e| vec![e])?;
+
+    read(&config, &files, &env::current_dir().unwrap())
 }
 
-fn parse_redirects(config: &mut Config) {
-    let mut redirects_hashmap = HashMap::new();
+/// Reads the settings out of the merged `config`, resolving paths relative to `pwd`.
+fn read(config: &Config, files: &[PathBuf], pwd: &Path) -> Result<Settings, Vec<BuildError>> {
+    let mut reader = Reader {
+        config,
+        files,
+        errors: Vec::new(),
+    };
+
+    reader.check_keys("", &["content", "templates", "redirects", "write_to_disk"]);
+    reader.check_keys(
+        "content",
+        &[
+            "path",
+            "page_types",
+            "syntax_theme",
+            "syntax_themes",
+            "syntax_highlighting",
+            "syntaxes_path",
+            "themes_path",
+            "archetypes_path",
+            "admonition_class",
+            "admonition_title_class",
+            "heading_anchors",
+            "math",
+            "smart_punctuation",
+            "emoji",
+            "external_links",
+            "lazy_images",
+            "summary_words",
+            "words_per_minute",
+            "toc_depth",
+        ],
+    );
+    reader.check_keys("templates", &["path", "globals"]);
+
+    let content = ContentSettings {
+        path: pwd.join(reader.get("content.path", String::from("content"))),
+        page_types: reader.list("content.page_types"),
+        syntax_theme: reader.get("content.syntax_theme", String::from("InspiredGitHub")),
+        syntax_themes: reader.get("content.syntax_themes", Vec::new()),
+        syntax_highlighting: reader.get("content.syntax_highlighting", SyntaxHighlighting::Inline),
+        syntaxes_path: pwd.join(reader.get("content.syntaxes_path", String::from("syntaxes"))),
+        themes_path: pwd.join(reader.get("content.themes_path", String::from("themes"))),
+        archetypes_path: pwd
+            .join(reader.get("content.archetypes_path", String::from("archetypes"))),
+        admonition_class: reader.get("content.admonition_class", String::from("admonition")),
+        admonition_title_class: reader.get(
+            "content.admonition_title_class",
+            String::from("admonition-title"),
+        ),
+        heading_anchors: reader.get("content.heading_anchors", false),
+        math: reader.get("content.math", false),
+        smart_punctuation: reader.get("content.smart_punctuation", false),
+        emoji: reader.get("content.emoji", false),
+        external_links: reader.get("content.external_links", false),
+        lazy_images: reader.get("content.lazy_images", false),
+        summary_words: reader.count("content.summary_words", 50, 0),
+        words_per_minute: reader.count("content.words_per_minute", 200, 1),
+        toc_depth: reader.count("content.toc_depth", 3, 0) as u32,
+    };
+
+    let templates = TemplatesSettings {
+        path: pwd.join(reader.get("templates.path", String::from("templates"))),
+        globals: reader.get("templates.globals", TeraMap::new()),
+    };
+
+    let mut redirects = HashMap::new();
 
-    if let Ok(redirects) = config.get_array("redirects") {
-        for redirect in redirects.into_iter() {
-            let redirect = redirect.into_table().unwrap();
-            let redirect_from = redirect.get("from").unwrap().to_string();
-            redirects_hashmap.insert(redirect_from, parse_redirect(redirect));
+    for redirect in reader.list::<Redirect>("redirects") {
+        if redirects.contains_key(&redirect.from) {
+            let error = BuildError::new(format!("Duplicate redirect from {}.", redirect.from));
+            reader
+                .errors
+                .push(reader.locate(error, "redirects", &redirect.from, None));
+            continue;
         }
+
+        redirects.insert(redirect.from.clone(), redirect);
     }
 
-    config.set("redirects", redirects_hashmap).unwrap();
+    let settings = Settings {
+        content,
+        templates,
+        redirects,
+        write_to_disk: reader.get("write_to_disk", false),
+    };
+
+    match reader.errors.is_empty() {
+        true => Ok(settings),
+        false => Err(reader.errors),
+    }
 }
 
-fn parse_redirect(redirect: HashMap<String, ConfigValue>) -> HashMap<String, String> {
-    let redirect_type = redirect.get("type").unwrap().to_string();
+/// Whether `path` is one of the config files `load` reads, in any format.
+pub fn is_config_file(path: &Path) -> bool {
+    let name = path.file_stem().and_then(|stem| stem.to_str());
+    let extension = path.extension().and_then(|ext| ext.to_str());
 
-    match redirect_type.as_ref() {
-        "permanent" | "temporary" => {}
-        _ => {
-            error!(
-                "Invalid redirect type '{}'. Valid values: permanent/temporary",
-                redirect_type
-            );
+    match (name, extension) {
+        (Some(name), Some(extension)) => {
+            CONFIG_NAMES.contains(&name) && CONFIG_EXTENSIONS.contains(&extension)
+        }
+        _ => false,
+    }
+}
 
-            process::exit(1);
+/// Finds `config/<name>` with any of the extensions the config crate reads.
+pub fn find_file(name: &str) -> Option<PathBuf> {
+    CONFIG_EXTENSIONS
+        .iter()
+        .map(|ext| Path::new(CONFIG_PATH).join(format!("{}.{}", name, ext)))
+        .find(|path| path.is_file())
+}
+
+/// Merges the config files into `config`, returning their paths in the order they were merged.
+fn merge_files(config: &mut Config) -> Result<Vec<PathBuf>, BuildError> {
+    let mut files = Vec::new();
+
+    match find_file("default") {
+        Some(path) => files.push(path),
+        None => {
+            return Err(BuildError::new(format!(
+                "Missing config file {}/default.yml.",
+                CONFIG_PATH
+            ))
+            .with_cause("a .toml, .json, .yaml, .hjson or .ini file works too"))
         }
+    }
+
+    let mode = env::var("THEA_ENV").unwrap_or("development".into());
+
+    match mode.as_ref() {
+        "development" | "production" => files.extend(find_file(&mode)),
+        _ => warn!("default/development/production are the only valid config file names."),
+    };
+
+    for path in &files {
+        config
+            .merge(ConfigFile::from(path.as_path()))
+            .map_err(config_error)?;
+    }
+
+    Ok(files)
+}
+
+/// Locates a config file parse error using the position reported by the file format's parser.
+fn config_error(e: ConfigError) -> BuildError {
+    let (uri, cause) = match e {
+        ConfigError::FileParse { uri, cause } => (uri, cause),
+        e => return BuildError::new("Failed to load the config.").with_cause(e),
     };
 
-    let mut parsed_redirects = HashMap::new();
-    parsed_redirects.insert("type".to_string(), redirect_type.to_string());
-    parsed_redirects.insert("to".to_string(), redirect.get("to").unwrap().to_string());
-    parsed_redirects
+    let mut error = BuildError::new("Failed to parse the config file.")
+        .with_cause(errors::without_position(&cause.to_string()));
+
+    if let Some(uri) = uri {
+        error = error.in_file(Path::new(&uri));
+    }
+
+    if let Some(e) = cause.downcast_ref::<ScanError>() {
+        error.at(e.marker().line(), Some(e.marker().col() + 1))
+    } else if let Some((line, col)) = cause
+        .downcast_ref::<toml::de::Error>()
+        .and_then(|e| e.line_col())
+    {
+        error.at(line + 1, Some(col + 1))
+    } else if let Some(e) = cause.downcast_ref::<serde_json::Error>() {
+        error.at(e.line(), Some(e.column()))
+    } else {
+        error
+    }
 }
 
-fn expand_paths(config: &mut Config) {
-    let pwd = env::current_dir().unwrap();
-    let path_fields = ["content.path", "templates.path"];
+impl<'a> Reader<'a> {
+    /// Reads `key`, falling back to `default` when it's missing or invalid.
+    fn get<T: DeserializeOwned>(&mut self, key: &str, default: T) -> T {
+        match self.config.get::<T>(key) {
+            Ok(value) => value,
+            Err(ConfigError::NotFound(_)) => default,
+            Err(e) => {
+                self.invalid(key, e);
+                default
+            }
+        }
+    }
+
+    /// Reads the `key` integer, which must be at least `min`. The config crate wraps negative
+    /// numbers around when reading unsigned ones, so they're read as `i64` first.
+    fn count(&mut self, key: &str, default: usize, min: usize) -> usize {
+        let count = self.get::<i64>(key, default as i64);
+
+        if count < min as i64 {
+            let error = BuildError::new(format!("Invalid value for `{}`.", key)).with_cause(
+                format!("expected a number of at least {}, found {}", min, count),
+            );
+            let name = key.rsplit('.').next().unwrap();
+            self.errors.push(self.locate(error, key, name, None));
+            return default;
+        }
+
+        count as usize
+    }
+
+    /// Reads the `key` array, skipping and reporting each invalid element.
+    fn list<T: DeserializeOwned>(&mut self, key: &str) -> Vec<T> {
+        let values = match self.config.get_array(key) {
+            Ok(values) => values,
+            Err(ConfigError::NotFound(_)) => return Vec::new(),
+            Err(e) => {
+                self.invalid(key, e);
+                return Vec::new();
+            }
+        };
+
+        let mut items = Vec::with_capacity(values.len());
+
+        for (i, value) in values.into_iter().enumerate() {
+            match value.try_into::<T>() {
+                Ok(item) => items.push(item),
+                Err(e) => self.invalid(&format!("{}[{}]", key, i), e),
+            }
+        }
+
+        items
+    }
+
+    /// Reports the keys of the `section` table, or of the top level, that aren't in `known`.
+    fn check_keys(&mut self, section: &str, known: &[&str]) {
+        let table = match section {
+            "" => self.config.collect().unwrap_or_default(),
+            _ => self.config.get_table(section).unwrap_or_default(),
+        };
+
+        let mut unknown = table
+            .keys()
+            .filter(|key| !known.contains(&key.as_str()))
+            .collect::<Vec<&String>>();
+
+        unknown.sort();
+
+        for key in unknown {
+            let path = match section {
+                "" => key.clone(),
+                _ => format!("{}.{}", section, key),
+            };
+
+            let error = BuildError::new(format!("Unknown setting `{}`.", path))
+                .with_cause(format!("valid keys: {}", known.join(", ")));
+
+            self.errors.push(self.locate(error, &path, key, None));
+        }
+    }
+
+    fn invalid(&mut self, key: &str, e: ConfigError) {
+        let (origin, cause) = match e {
+            ConfigError::Type {
+                origin,
+                unexpected,
+                expected,
+                ..
+            } => (
+                origin,
+                format!("expected {}, found {}", expected, unexpected),
+            ),
+            e => (None, e.to_string()),
+        };
+
+        let name = key.rsplit('.').next().unwrap();
+        let name = name.split('[').next().unwrap();
+        let error = BuildError::new(format!("Invalid value for `{}`.", key)).with_cause(cause);
+
+        self.errors.push(self.locate(error, key, name, origin));
+    }
+
+    /// Points `error` about `key` at the first mention of `needle` in the file the value came
+    /// from or, when that's unknown, the last config file mentioning it.
+    fn locate(
+        &self,
+        error: BuildError,
+        key: &str,
+        needle: &str,
+        origin: Option<String>,
+    ) -> BuildError {
+        let top_level = key.split(['.', '[']).next().unwrap();
+        let candidates = origin
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(self.files.iter().rev().cloned());
+
+        for path in candidates {
+            let contents = fs::read_to_string(&path).unwrap_or_default();
+
+            if contents.contains(top_level) && contents.contains(needle) {
+                return error.at_mention(&path, needle);
+            }
+        }
 
-    for path_field in &path_fields {
-        let path_str = config.get_str(path_field).unwrap();
-        let mut path_buf = pwd.clone();
-        path_buf.push(path_str);
-        let path_buf_string = path_buf.as_path().to_str().unwrap();
-        config.set(path_field, path_buf_string).unwrap();
+        error
     }
 }
@@ -0,0 +1,247 @@
+use crate::errors::BuildError;
+use crate::markdown;
+use std::path::Path;
+use tera::{Context as TeraContext, Tera, Value as TeraValue};
+
+const TEMPLATES_DIR: &str = "shortcodes";
+
+struct Call {
+    name: String,
+    args: Vec<(String, TeraValue)>,
+}
+
+/// Expands `{{ name(arg=value) }}` and `{% name(arg=value) %}body{% end %}` shortcodes by rendering
+/// `templates/shortcodes/<name>.html`. Fenced code blocks and inline code spans are left as is.
+pub fn expand(
+    content: &str,
+    path: &Path,
+    line_offset: usize,
+    templates: &Tera,
+) -> Result<String, BuildError> {
+    let mut expanded = String::with_capacity(content.len());
+    let mut pos = 0;
+
+    for region in markdown::fenced_regions(content) {
+        // Block shortcode bodies can run past the start of a region.
+        if region.range.end <= pos {
+            continue;
+        }
+
+        if region.is_code {
+            expanded += &content[pos.max(region.range.start)..region.range.end];
+            pos = region.range.end;
+            continue;
+        }
+
+        while pos < region.range.end {
+            pos = expand_at(content, pos, &mut expanded, path, line_offset, templates)?;
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Expands the shortcode, code span or character at byte `pos` of `content`, returning the
+/// position following it.
+fn expand_at(
+    content: &str,
+    pos: usize,
+    expanded: &mut String,
+    path: &Path,
+    line_offset: usize,
+    templates: &Tera,
+) -> Result<usize, BuildError> {
+    let rest = &content[pos..];
+    let err = |msg: String| {
+        BuildError::new(msg)
+            .in_file(path)
+            .at_offset(content, pos, line_offset)
+    };
+
+    if rest.starts_with('`') {
+        let ticks = rest.len() - rest.trim_start_matches('`').len();
+        let delimiter = &rest[..ticks];
+        let span_len = match rest[ticks..].find(delimiter) {
+            Some(i) => i + ticks * 2,
+            None => ticks,
+        };
+
+        *expanded += &rest[..span_len];
+        Ok(pos + span_len)
+    } else if let Some(inner) = rest.strip_prefix("{{") {
+        let call = inner.find("}}").and_then(|end| parse_call(&inner[..end]));
+
+        match call {
+            Some(call) => {
+                let call_len = rest.find("}}").unwrap() + 2;
+                *expanded += &render(&call, None, templates).map_err(err)?;
+                Ok(pos + call_len)
+            }
+            None => {
+                *expanded += "{{";
+                Ok(pos + 2)
+            }
+        }
+    } else if let Some(inner) = rest.strip_prefix("{%") {
+        let call = inner.find("%}").and_then(|end| parse_call(&inner[..end]));
+
+        match call {
+            Some(call) => {
+                let open_len = rest.find("%}").unwrap() + 2;
+                let (body, body_and_end_len) = match find_end(&rest[open_len..]) {
+                    Some(found) => found,
+                    None => {
+                        return Err(err(format!(
+                            "Shortcode '{}' is missing its {{% end %}} tag.",
+                            call.name
+                        )))
+                    }
+                };
+
+                let body_line_offset =
+                    line_offset + content[..pos + open_len].matches('\n').count();
+                let body = expand(body, path, body_line_offset, templates)?;
+
+                *expanded += &render(&call, Some(&body), templates).map_err(err)?;
+                Ok(pos + open_len + body_and_end_len)
+            }
+            None => {
+                *expanded += "{%";
+                Ok(pos + 2)
+            }
+        }
+    } else {
+        let c = rest.chars().next().unwrap();
+        expanded.push(c);
+        Ok(pos + c.len_utf8())
+    }
+}
+
+/// Finds the `{% end %}` closing the current block, skipping over nested blocks. Returns the
+/// body and the length of the body plus the closing tag.
+fn find_end(content: &str) -> Option<(&str, usize)> {
+    let mut depth = 0;
+    let mut pos = 0;
+
+    while let Some(i) = content[pos..].find("{%") {
+        let tag_start = pos + i;
+        let tag_end = tag_start + 2 + content[tag_start + 2..].find("%}")?;
+        let tag = content[tag_start + 2..tag_end].trim();
+
+        if tag == "end" {
+            if depth == 0 {
+                return Some((&content[..tag_start], tag_end + 2));
+            }
+
+            depth -= 1;
+        } else if parse_call(tag).is_some() {
+            depth += 1;
+        }
+
+        pos = tag_end + 2;
+    }
+
+    None
+}
+
+fn parse_call(call: &str) -> Option<Call> {
+    let call = call.trim();
+    let open = call.find('(')?;
+
+    if !call.ends_with(')') {
+        return None;
+    }
+
+    let name = call[..open].trim();
+    let is_ident = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if !is_ident {
+        return None;
+    }
+
+    Some(Call {
+        name: name.to_string(),
+        args: parse_args(&call[open + 1..call.len() - 1])?,
+    })
+}
+
+fn parse_args(args: &str) -> Option<Vec<(String, TeraValue)>> {
+    let mut parsed = Vec::new();
+    let mut rest = args.trim();
+
+    while !rest.is_empty() {
+        let eq = rest.find('=')?;
+        let key = rest[..eq].trim().to_string();
+        rest = rest[eq + 1..].trim_start();
+
+        let (value, consumed) = match rest.chars().next()? {
+            quote @ '"' | quote @ '\'' => {
+                let end = rest[1..].find(quote)? + 1;
+                (TeraValue::from(&rest[1..end]), end + 1)
+            }
+            _ => {
+                let end = rest.find(',').unwrap_or(rest.len());
+                let raw = rest[..end].trim();
+                let value = match raw {
+                    "true" => TeraValue::from(true),
+                    "false" => TeraValue::from(false),
+                    _ => match raw.parse::<i64>() {
+                        Ok(i) => TeraValue::from(i),
+                        Err(_) => TeraValue::from(raw.parse::<f64>().ok()?),
+                    },
+                };
+                (value, end)
+            }
+        };
+
+        parsed.push((key, value));
+        rest = rest[consumed..].trim_start();
+
+        if let Some(after_comma) = rest.strip_prefix(',') {
+            rest = after_comma.trim_start();
+        } else if !rest.is_empty() {
+            return None;
+        }
+    }
+
+    Some(parsed)
+}
+
+fn render(call: &Call, body: Option<&str>, templates: &Tera) -> Result<String, String> {
+    let template_name = format!("{}/{}.html", TEMPLATES_DIR, call.name);
+
+    if !templates.templates.contains_key(&template_name) {
+        return Err(format!(
+            "Unknown shortcode '{}'; expected a template at templates/{}.",
+            call.name, template_name
+        ));
+    }
+
+    let mut context = TeraContext::new();
+
+    for (key, value) in &call.args {
+        context.insert(key.as_str(), value);
+    }
+
+    if let Some(body) = body {
+        context.insert("body", body);
+    }
+
+    templates
+        .render(&template_name, &context)
+        .map(|rendered| rendered.trim().to_string())
+        .map_err(|e| {
+            let mut msg = format!("Failed to render shortcode '{}': {}", call.name, e);
+            let mut cause = std::error::Error::source(&e);
+
+            while let Some(e) = cause {
+                msg += &format!(" - {}", e);
+                cause = e.source();
+            }
+
+            msg
+        })
+}
@@ -0,0 +1,146 @@
+use crate::errors::BuildError;
+use crate::formats;
+use std::path::Path;
+use yaml_rust::Yaml;
+
+pub fn slugify(s: &str) -> String {
+    let mut slug = String::with_capacity(s.len());
+    let mut pending_dash = false;
+
+    for c in s.chars() {
+        if c.is_alphanumeric() {
+            if pending_dash && !slug.is_empty() {
+                slug.push('-');
+            }
+
+            pending_dash = false;
+            slug.extend(c.to_lowercase());
+        } else if is_combining_mark(c) {
+            continue;
+        } else {
+            pending_dash = true;
+        }
+    }
+
+    slug
+}
+
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036f}' | '\u{1ab0}'..='\u{1aff}' | '\u{20d0}'..='\u{20ff}'
+    )
+}
+
+/// Derives a slug from a path relative to the page type directory, e.g. `guides/Setup.md`
+/// becomes `/guides/setup` and both `guides/index.md` and `guides/_index.md` become `/guides`.
+/// Index files served as is, such as `feeds/index.xml`, keep their name.
+pub fn derive_slug(rel_path: &Path) -> String {
+    let mut parts = rel_dir_parts(rel_path);
+    let stem = rel_path.file_stem().and_then(|s| s.to_str());
+    let extension = rel_path.extension().and_then(|e| e.to_str());
+    let is_index = match (stem, extension) {
+        (Some(stem), Some(ext)) => (stem == "index" || stem == "_index") && formats::is_page(ext),
+        _ => false,
+    };
+
+    if !is_index {
+        parts.push(filename_part(rel_path));
+    }
+
+    format!("/{}", parts.join("/"))
+}
+
+/// Expands a page type permalink pattern such as `/blog/:year/:month/:filename`.
+pub fn expand_permalink(pattern: &str, rel_path: &Path, fm: &Yaml) -> Result<String, BuildError> {
+    let mut segments = Vec::new();
+
+    for segment in pattern.split('/').filter(|s| !s.is_empty()) {
+        let expanded = match segment {
+            ":year" => date_part(fm, pattern, 0)?,
+            ":month" => date_part(fm, pattern, 1)?,
+            ":day" => date_part(fm, pattern, 2)?,
+            ":title" => match fm["title"].as_str() {
+                Some(title) => slugify(title),
+                None => return Err(missing_key(pattern, "title")),
+            },
+            ":filename" => filename_part(rel_path),
+            ":path" => {
+                let mut parts = rel_dir_parts(rel_path);
+                parts.push(filename_part(rel_path));
+                parts.join("/")
+            }
+            s if s.starts_with(':') => {
+                return Err(BuildError::new(format!(
+                    "Unknown permalink token '{}' in '{}'.",
+                    s, pattern
+                )))
+            }
+            s => s.to_string(),
+        };
+
+        if !expanded.is_empty() {
+            segments.push(expanded);
+        }
+    }
+
+    Ok(format!("/{}", segments.join("/")))
+}
+
+fn rel_dir_parts(rel_path: &Path) -> Vec<String> {
+    match rel_path.parent() {
+        Some(parent) => parent
+            .components()
+            .map(|c| slugify(&c.as_os_str().to_string_lossy()))
+            .filter(|c| !c.is_empty())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Slugifies the stem of pages. Other files keep their name so that links to assets such as
+/// `jquery.min.js` keep working.
+fn filename_part(rel_path: &Path) -> String {
+    match rel_path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if !formats::is_page(ext) => rel_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        _ => rel_path
+            .file_stem()
+            .map(|stem| slugify(&stem.to_string_lossy()))
+            .unwrap_or_default(),
+    }
+}
+
+fn date_part(fm: &Yaml, pattern: &str, index: usize) -> Result<String, BuildError> {
+    let date = match fm["date"].as_str() {
+        Some(date) => date,
+        None => return Err(missing_key(pattern, "date")),
+    };
+
+    let parts = date
+        .get(0..10)
+        .map(|d| d.split('-').collect::<Vec<&str>>())
+        .unwrap_or_default();
+
+    let is_valid = parts.len() == 3
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+
+    match is_valid {
+        true => Ok(parts[index].to_string()),
+        false => Err(BuildError::new(format!(
+            "Failed to parse date '{}'; expected YYYY-MM-DD.",
+            date
+        ))),
+    }
+}
+
+fn missing_key(pattern: &str, key: &str) -> BuildError {
+    BuildError::new(format!(
+        "Permalink '{}' requires a {} key in frontmatter.",
+        pattern, key
+    ))
+}
@@ -0,0 +1,131 @@
+use crate::formats::Format;
+use crate::settings;
+
+pub const MORE_MARKER: &str = "<!-- more -->";
+
+pub struct Summary {
+    pub summary: String,
+    pub word_count: usize,
+    pub reading_time: usize,
+}
+
+/// Computes the summary, word count and reading time of a page. `content` is the raw source,
+/// `parsed_content` the rendered HTML and `md_summary` the Markdown renderer's HTML up to the
+/// `<!-- more -->` marker, if any.
+pub fn from(
+    content: &str,
+    parsed_content: &str,
+    format: &Format,
+    md_summary: Option<String>,
+) -> Summary {
+    let text = match format {
+        Format::Markup(_) | Format::Document { .. } | Format::Html => to_plain_text(parsed_content),
+        Format::Raw => {
+            return Summary {
+                summary: String::new(),
+                word_count: 0,
+                reading_time: 0,
+            }
+        }
+    };
+
+    let words = text.split_whitespace().collect::<Vec<&str>>();
+    let words_per_minute = settings::current().content.words_per_minute;
+    let summary = match (md_summary, content.find(MORE_MARKER)) {
+        (Some(summary), _) => summary,
+        (None, Some(i)) if matches!(format, Format::Html) => content[..i].to_string(),
+        _ => truncate_words(&words),
+    };
+
+    Summary {
+        summary,
+        word_count: words.len(),
+        reading_time: words.len().div_ceil(words_per_minute),
+    }
+}
+
+fn truncate_words(words: &[&str]) -> String {
+    let summary_words = settings::current().content.summary_words;
+
+    match words.len() > summary_words {
+        true => format!("{}…", words[..summary_words].join(" ")),
+        false => words.join(" "),
+    }
+}
+
+/// Elements removed before counting words: scripts and the markup the renderer adds around
+/// content, i.e. code line numbers, heading anchors and callout markers.
+const HIDDEN_ELEMENTS: &[(&str, &str)] = &[
+    ("<script", "</script>"),
+    ("<span class=\"line-nb\"", "</span>"),
+    ("<a class=\"heading-anchor\"", "</a>"),
+    ("<a class=\"callout\"", "</a>"),
+];
+
+/// Strips tags and hidden elements from rendered HTML and decodes the entities pulldown-cmark
+/// escapes.
+pub fn to_plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    let without_hidden = HIDDEN_ELEMENTS
+        .iter()
+        .fold(html.to_string(), |html, (open, close)| {
+            remove_elements(&html, open, close)
+        });
+
+    for c in without_hidden.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn remove_elements(html: &str, open: &str, close: &str) -> String {
+    let mut kept = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find(open) {
+        kept += &rest[..start];
+        rest = match rest[start..].find(close) {
+            Some(end) => &rest[start + end + close.len()..],
+            None => "",
+        };
+    }
+
+    kept + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_skips_generated_markup() {
+        let html = concat!(
+            "<h2 id=\"setup\">Setup<a class=\"heading-anchor\" href=\"#setup\">#</a></h2>",
+            "<pre><code><div class=\"line\"><span class=\"line-nb\">1</span>let x = 1;",
+            "<a class=\"callout\" id=\"cb0-callout-1-ref\" href=\"#cb0-callout-1\">1</a></div>",
+            "</code></pre><ol class=\"callouts\"><li id=\"cb0-callout-1\" value=\"1\">",
+            "<a class=\"callout\" href=\"#cb0-callout-1-ref\">1</a> Binds x</li></ol>",
+            "<script>let hidden = true;</script>",
+        );
+        let words = to_plain_text(html)
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<String>>();
+
+        assert_eq!(words, ["Setup", "let", "x", "=", "1;", "Binds", "x"]);
+    }
+}
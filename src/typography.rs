@@ -0,0 +1,189 @@
+use crate::emoji;
+use crate::markdown::RenderOptions;
+use pulldown_cmark::{Event, Tag};
+
+/// The optional rewrites of Markdown events enabled by the `content.smart_punctuation`,
+/// `content.emoji`, `content.external_links` and `content.lazy_images` settings, or the
+/// frontmatter keys of the same names.
+pub struct Typography {
+    smart_punctuation: bool,
+    emoji: bool,
+    external_links: bool,
+    lazy_images: bool,
+    previous: Option<char>,
+    in_code_block: bool,
+    image: Option<OpenImage>,
+}
+
+struct OpenImage {
+    dest: String,
+    title: String,
+    alt: String,
+}
+
+impl Typography {
+    pub fn new(opts: &RenderOptions) -> Typography {
+        Typography {
+            smart_punctuation: opts.smart_punctuation,
+            emoji: opts.emoji,
+            external_links: opts.external_links,
+            lazy_images: opts.lazy_images,
+            previous: None,
+            in_code_block: false,
+            image: None,
+        }
+    }
+
+    /// Rewrites an event, or returns `None` while buffering an image's alt text.
+    pub fn apply<'a>(&mut self, event: Event<'a>) -> Option<Event<'a>> {
+        if let Some(ref mut image) = self.image {
+            match event {
+                Event::End(Tag::Image(..)) => {
+                    let image = self.image.take().unwrap();
+                    return Some(Event::Html(image.to_html().into()));
+                }
+                Event::Text(text) | Event::Code(text) => image.alt.push_str(&text),
+                _ => {}
+            }
+
+            return None;
+        }
+
+        let event = match event {
+            Event::Start(Tag::CodeBlock(info)) => {
+                self.in_code_block = true;
+                Event::Start(Tag::CodeBlock(info))
+            }
+            Event::End(Tag::CodeBlock(info)) => {
+                self.in_code_block = false;
+                Event::End(Tag::CodeBlock(info))
+            }
+            Event::Text(text) if !self.in_code_block => {
+                let mut text = text.to_string();
+
+                if self.emoji {
+                    text = emoji::replace(&text);
+                }
+
+                if self.smart_punctuation {
+                    text = self.smarten(&text);
+                }
+
+                Event::Text(text.into())
+            }
+            Event::Start(Tag::Link(_, ref dest, ref title))
+                if self.external_links && is_external(dest) =>
+            {
+                let title = match title.is_empty() {
+                    true => String::new(),
+                    false => format!(" title=\"{}\"", escape_attribute(title)),
+                };
+
+                Event::Html(
+                    format!(
+                        "<a href=\"{}\"{} rel=\"noopener noreferrer\" target=\"_blank\">",
+                        escape_attribute(dest),
+                        title
+                    )
+                    .into(),
+                )
+            }
+            Event::Start(Tag::Image(_, dest, title)) if self.lazy_images => {
+                self.image = Some(OpenImage {
+                    dest: dest.to_string(),
+                    title: title.to_string(),
+                    alt: String::new(),
+                });
+                return None;
+            }
+            Event::Start(ref tag) | Event::End(ref tag) if is_block(tag) => {
+                self.previous = None;
+                event
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                self.previous = Some(' ');
+                event
+            }
+            Event::Code(ref code) => {
+                self.previous = code.chars().last();
+                event
+            }
+            event => event,
+        };
+
+        Some(event)
+    }
+
+    /// Replaces straight quotes with curly ones, `--` and `---` with en and em dashes and `...`
+    /// with an ellipsis. A quote is opening at the start of a block or after whitespace or an
+    /// opening bracket, and closing otherwise.
+    fn smarten(&mut self, text: &str) -> String {
+        let chars = text.chars().collect::<Vec<char>>();
+        let mut smart = String::with_capacity(text.len());
+        let mut i = 0;
+
+        while i < chars.len() {
+            let opening = self
+                .previous
+                .is_none_or(|p| p.is_whitespace() || "([{-–—“‘".contains(p));
+
+            let (replacement, len) = match chars[i..] {
+                ['"', ..] if opening => ('“', 1),
+                ['"', ..] => ('”', 1),
+                ['\'', ..] if opening => ('‘', 1),
+                ['\'', ..] => ('’', 1),
+                ['-', '-', '-', ..] => ('—', 3),
+                ['-', '-', ..] => ('–', 2),
+                ['.', '.', '.', ..] => ('…', 3),
+                [c, ..] => (c, 1),
+                [] => unreachable!(),
+            };
+
+            smart.push(replacement);
+            self.previous = Some(replacement);
+            i += len;
+        }
+
+        smart
+    }
+}
+
+impl OpenImage {
+    fn to_html(&self) -> String {
+        let title = match self.title.is_empty() {
+            true => String::new(),
+            false => format!(" title=\"{}\"", escape_attribute(&self.title)),
+        };
+
+        format!(
+            "<img src=\"{}\" alt=\"{}\"{} loading=\"lazy\" decoding=\"async\" />",
+            escape_attribute(&self.dest),
+            escape_attribute(&self.alt),
+            title
+        )
+    }
+}
+
+fn is_block(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Paragraph
+            | Tag::Heading(_)
+            | Tag::BlockQuote
+            | Tag::Item
+            | Tag::TableCell
+            | Tag::FootnoteDefinition(_)
+    )
+}
+
+fn is_external(dest: &str) -> bool {
+    dest.starts_with("http://") || dest.starts_with("https://") || dest.starts_with("//")
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -1,5 +1,9 @@
-use crate::{rebuild_site, SETTINGS};
-use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use crate::settings::{self, CONFIG_PATH};
+use crate::{rebuild_site, CONTENT};
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::env;
+use std::path::PathBuf;
 use std::sync::mpsc::channel;
 use std::thread;
 use std::time::Duration;
@@ -8,26 +12,41 @@ pub fn watch_files() {
     thread::spawn(move || {
         let (tx, rx) = channel();
         let mut watcher = watcher(tx, Duration::from_secs(1)).unwrap();
-        let content_path = SETTINGS.get_str("content.path").unwrap();
-        let templates_path = SETTINGS.get_str("templates.path").unwrap();
+        let config_path = env::current_dir().unwrap().join(CONFIG_PATH);
+        let mut site_dirs = Vec::new();
+        let mut watched_dirs = HashSet::new();
 
         let _ = watcher
-            .watch(content_path, RecursiveMode::Recursive)
+            .watch(&config_path, RecursiveMode::NonRecursive)
             .map_err(|e| error!("Watcher error: {}", e));
 
-        let _ = watcher
-            .watch(templates_path, RecursiveMode::Recursive)
-            .map_err(|e| error!("Watcher error: {}", e));
+        watch_site_dirs(&mut watcher, &mut site_dirs);
+        watch_dependencies(&mut watcher, &mut watched_dirs);
 
         loop {
             match rx.recv() {
                 Ok(event) => match event {
-                    DebouncedEvent::Create(_)
-                    | DebouncedEvent::Write(_)
-                    | DebouncedEvent::Rename(_, _)
-                    | DebouncedEvent::Remove(_) => {
+                    DebouncedEvent::Create(path)
+                    | DebouncedEvent::Write(path)
+                    | DebouncedEvent::Rename(_, path)
+                    | DebouncedEvent::Remove(path) => {
+                        if path.starts_with(&config_path) {
+                            if !settings::is_config_file(&path) {
+                                continue;
+                            }
+
+                            info!("Config file changed; reloading the config.");
+
+                            if !settings::reload() {
+                                continue;
+                            }
+
+                            watch_site_dirs(&mut watcher, &mut site_dirs);
+                        }
+
                         info!("File system event occurred; regenerating the HashMap.");
                         rebuild_site();
+                        watch_dependencies(&mut watcher, &mut watched_dirs);
                     }
                     _ => {}
                 },
@@ -36,3 +55,59 @@ pub fn watch_files() {
         }
     });
 }
+
+/// Watches the content and templates directories, moving the watches when a config reload
+/// changed their paths.
+fn watch_site_dirs(watcher: &mut RecommendedWatcher, site_dirs: &mut Vec<PathBuf>) {
+    let settings = settings::current();
+    let dirs = vec![
+        settings.content.path.clone(),
+        settings.templates.path.clone(),
+    ];
+
+    if *site_dirs == dirs {
+        return;
+    }
+
+    for dir in site_dirs.iter() {
+        let _ = watcher.unwatch(dir);
+    }
+
+    for dir in &dirs {
+        let _ = watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .map_err(|e| error!("Watcher error: {}", e));
+    }
+
+    *site_dirs = dirs;
+}
+
+/// Watches the directories of files pages depend on, such as code included in code blocks, that
+/// live outside the content and templates directories.
+fn watch_dependencies(watcher: &mut RecommendedWatcher, watched_dirs: &mut HashSet<PathBuf>) {
+    let settings = settings::current();
+    let content_path = &settings.content.path;
+    let templates_path = &settings.templates.path;
+
+    let dependency_dirs = CONTENT
+        .read()
+        .unwrap()
+        .values()
+        .flat_map(|page| page.meta.dependencies.iter())
+        .filter(|dep| !dep.starts_with(content_path) && !dep.starts_with(templates_path))
+        .filter_map(|dep| dep.parent().map(|dir| dir.to_path_buf()))
+        .collect::<HashSet<PathBuf>>();
+
+    for dir in dependency_dirs {
+        if watched_dirs.contains(&dir) {
+            continue;
+        }
+
+        match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            Ok(_) => {
+                watched_dirs.insert(dir);
+            }
+            Err(e) => error!("Watcher error: {}", e),
+        }
+    }
+}